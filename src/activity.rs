@@ -0,0 +1,120 @@
+use std::time::{Duration, Instant};
+
+use sqlx::{AnyPool, Executor};
+use tracing::warn;
+
+use super::{AromaticError, Backend};
+
+const RETRY_INTERVAL: Duration = Duration::from_millis(500);
+
+/// What a run does when other sessions hold transactions open on the database, as
+/// their locks would otherwise make it fail halfway through. From
+/// `MIGRATION_OPEN_TRANSACTIONS`: `warn`, `wait` or `abort`, anything else ignores
+/// them.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OpenTransactionsPolicy {
+    #[default]
+    Ignore,
+    /// Logs them and runs anyway.
+    Warn,
+    /// Waits for them to finish, at most the lock timeout, then aborts.
+    Wait,
+    /// Fails before running anything.
+    Abort,
+}
+
+impl OpenTransactionsPolicy {
+    pub(crate) fn from_env() -> Self {
+        match std::env::var("MIGRATION_OPEN_TRANSACTIONS").as_deref() {
+            Ok("warn") => Self::Warn,
+            Ok("wait") => Self::Wait,
+            Ok("abort") => Self::Abort,
+            _ => Self::Ignore,
+        }
+    }
+
+    /// Checks for open transactions and applies the policy, `timeout` is how long
+    /// `Wait` waits.
+    pub(crate) async fn guard(
+        self,
+        backend: Backend,
+        pool: &AnyPool,
+        timeout: Duration,
+    ) -> Result<(), AromaticError> {
+        if self == Self::Ignore {
+            return Ok(());
+        }
+        let started = Instant::now();
+        loop {
+            let open = open_transactions(backend, pool).await?;
+            if open == 0 {
+                return Ok(());
+            }
+            match self {
+                Self::Warn => {
+                    warn!(
+                        function = "OpenTransactionsPolicy::guard",
+                        message = format!(
+                            "{open} other session(s) hold transactions open, the \
+                             migrations may wait on their locks"
+                        ),
+                    );
+                    return Ok(());
+                },
+                Self::Wait if started.elapsed() < timeout => {
+                    tokio::time::sleep(RETRY_INTERVAL).await;
+                },
+                _ => return Err(AromaticError::OpenTransactions(open)),
+            }
+        }
+    }
+}
+
+/// Sessions other than this one in the middle of a transaction. SQLite doesn't list
+/// them, it only tells whether one holds the write lock by failing to take it.
+async fn open_transactions(backend: Backend, pool: &AnyPool) -> Result<i64, sqlx::Error> {
+    match backend {
+        Backend::Sqlite => sqlite_write_locked(pool).await.map(i64::from),
+        #[cfg(feature = "postgres")]
+        Backend::Postgres => {
+            sqlx::query_scalar(
+                "SELECT COUNT(*) FROM pg_stat_activity \
+                 WHERE datname = current_database() AND pid <> pg_backend_pid() \
+                 AND xact_start IS NOT NULL;",
+            )
+            .fetch_one(pool)
+            .await
+        },
+        #[cfg(feature = "mysql")]
+        Backend::MySql => {
+            sqlx::query_scalar(
+                "SELECT COUNT(*) FROM information_schema.innodb_trx \
+                 WHERE trx_mysql_thread_id <> CONNECTION_ID();",
+            )
+            .fetch_one(pool)
+            .await
+        },
+    }
+}
+
+/// Tries to take the write lock without waiting for it, the busy timeout of the
+/// connection is restored afterwards.
+async fn sqlite_write_locked(pool: &AnyPool) -> Result<bool, sqlx::Error> {
+    let mut connection = pool.acquire().await?;
+    let busy_timeout: i64 = sqlx::query_scalar("PRAGMA busy_timeout;")
+        .fetch_one(&mut *connection)
+        .await?;
+    connection.execute("PRAGMA busy_timeout = 0;").await?;
+    let locked = match connection.execute("BEGIN IMMEDIATE;").await {
+        Ok(_) => {
+            connection.execute("ROLLBACK;").await?;
+            false
+        },
+        Err(sqlx::Error::Database(err)) if err.code().as_deref() == Some("5") => true,
+        Err(err) => return Err(err),
+    };
+    connection
+        .execute(format!("PRAGMA busy_timeout = {busy_timeout};").as_str())
+        .await?;
+    Ok(locked)
+}
@@ -0,0 +1,360 @@
+use sqlparser::{dialect, parser::Parser};
+#[cfg(feature = "postgres")]
+use sqlx::postgres::{PgDatabaseError, PgErrorPosition};
+
+use super::Orm;
+
+/// The databases the migration runner knows how to talk to, picked from the scheme of
+/// `DATABASE_URL`. Everything goes through sqlx's `Any` driver, this only carries
+/// the bits of SQL that differ between them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    Sqlite,
+    #[cfg(feature = "postgres")]
+    Postgres,
+    #[cfg(feature = "mysql")]
+    MySql,
+}
+
+impl Backend {
+    pub fn from_url(url: &str) -> Option<Self> {
+        match url.split_once(':').map(|(scheme, _)| scheme) {
+            Some("sqlite") => Some(Self::Sqlite),
+            #[cfg(feature = "postgres")]
+            Some("postgres" | "postgresql") => Some(Self::Postgres),
+            #[cfg(feature = "mysql")]
+            Some("mysql" | "mariadb") => Some(Self::MySql),
+            _ => None,
+        }
+    }
+
+    /// Bind parameter for the `index`-th (starting at 1) argument of a query.
+    #[cfg_attr(not(feature = "postgres"), allow(unused_variables))]
+    pub fn placeholder(&self, index: usize) -> String {
+        match self {
+            Self::Sqlite => "?".to_string(),
+            #[cfg(feature = "postgres")]
+            Self::Postgres => format!("${index}"),
+            #[cfg(feature = "mysql")]
+            Self::MySql => "?".to_string(),
+        }
+    }
+
+    pub fn placeholders(&self, count: usize) -> String {
+        (1..=count)
+            .map(|i| self.placeholder(i))
+            .collect::<Vec<String>>()
+            .join(",")
+    }
+
+    /// Casts `expression` to a type the `Any` driver decodes as a string.
+    pub fn cast_to_text(&self, expression: &str) -> String {
+        match self {
+            #[cfg(feature = "mysql")]
+            Self::MySql => format!("CAST({expression} AS CHAR)"),
+            _ => format!("CAST({expression} AS TEXT)"),
+        }
+    }
+
+    /// Quotes `identifier` so it can be used as a table or schema name whatever it
+    /// contains.
+    pub(crate) fn quote_identifier(&self, identifier: &str) -> String {
+        match self {
+            #[cfg(feature = "mysql")]
+            Self::MySql => format!("`{}`", identifier.replace('`', "``")),
+            _ => format!("\"{}\"", identifier.replace('"', "\"\"")),
+        }
+    }
+
+    /// Whether a backslash escapes the next character inside a quoted string.
+    pub(crate) fn backslash_escapes(&self) -> bool {
+        match self {
+            Self::Sqlite => false,
+            #[cfg(feature = "postgres")]
+            Self::Postgres => false,
+            #[cfg(feature = "mysql")]
+            Self::MySql => true,
+        }
+    }
+
+    /// `INSERT` of a single row that does nothing if it conflicts with an existing one.
+    pub(crate) fn insert_or_ignore(
+        &self,
+        table: &str,
+        columns: &str,
+        values: &str,
+    ) -> String {
+        match self {
+            Self::Sqlite => Orm::insert_or_ignore(table)
+                .set_columns(columns)
+                .add_value(values)
+                .ready(),
+            #[cfg(feature = "postgres")]
+            Self::Postgres => Orm::insert(table)
+                .set_columns(columns)
+                .add_value(values)
+                .on_conflict_do_nothing()
+                .ready(),
+            #[cfg(feature = "mysql")]
+            Self::MySql => Orm::insert_ignore(table)
+                .set_columns(columns)
+                .add_value(values)
+                .ready(),
+        }
+    }
+
+    /// Condition matching rows whose `column` is more than `days` days in the past.
+    pub(crate) fn older_than(&self, column: &str, days: u32) -> String {
+        match self {
+            Self::Sqlite => format!("{column} < datetime('now', '-{days} days')"),
+            #[cfg(feature = "postgres")]
+            Self::Postgres => format!("{column} < NOW() - INTERVAL '{days} days'"),
+            #[cfg(feature = "mysql")]
+            Self::MySql => format!("{column} < NOW() - INTERVAL {days} DAY"),
+        }
+    }
+
+    /// Deletes at most `limit` rows of `table` matching `condition`, only MySQL
+    /// supports `LIMIT` on `DELETE` so the others go through the row ids.
+    pub(crate) fn delete_batch(
+        &self,
+        table: &str,
+        condition: &str,
+        limit: u32,
+    ) -> String {
+        let row_id = match self {
+            Self::Sqlite => "rowid",
+            #[cfg(feature = "postgres")]
+            Self::Postgres => "ctid",
+            #[cfg(feature = "mysql")]
+            Self::MySql => {
+                return Orm::delete(&format!("FROM {table}"))
+                    .where_()
+                    .condition(condition)
+                    .limit(limit)
+                    .ready()
+            },
+        };
+        let batch = Orm::select(row_id)
+            .from(table)
+            .where_()
+            .condition(condition)
+            .limit(limit)
+            .ready();
+        Orm::delete(&format!("FROM {table}"))
+            .where_()
+            .condition(&format!("{row_id} IN ({})", batch.trim_end_matches(';')))
+            .ready()
+    }
+
+    /// Parses `sql` with the dialect of the database, without running it, the error
+    /// points to the first statement that isn't valid.
+    pub(crate) fn parse_sql(&self, sql: &str) -> Result<(), String> {
+        let result = match self {
+            Self::Sqlite => Parser::parse_sql(&dialect::SQLiteDialect {}, sql),
+            #[cfg(feature = "postgres")]
+            Self::Postgres => Parser::parse_sql(&dialect::PostgreSqlDialect {}, sql),
+            #[cfg(feature = "mysql")]
+            Self::MySql => Parser::parse_sql(&dialect::MySqlDialect {}, sql),
+        };
+        result.map(|_| ()).map_err(|err| err.to_string())
+    }
+
+    /// Byte offset in the executed SQL of what made `err` fail, only Postgres reports
+    /// it.
+    #[cfg_attr(not(feature = "postgres"), allow(unused_variables))]
+    pub(crate) fn error_offset(&self, err: &sqlx::Error, sql: &str) -> Option<usize> {
+        match self {
+            #[cfg(feature = "postgres")]
+            Self::Postgres => {
+                let err = err
+                    .as_database_error()?
+                    .try_downcast_ref::<PgDatabaseError>()?;
+                match err.position()? {
+                    // counted in characters starting at 1
+                    PgErrorPosition::Original(position) => sql
+                        .char_indices()
+                        .nth(position.saturating_sub(1))
+                        .map(|(offset, _)| offset),
+                    PgErrorPosition::Internal { .. } => None,
+                }
+            },
+            _ => None,
+        }
+    }
+
+    /// Query returning the version of the database server as text.
+    pub(crate) fn version_query(&self) -> &'static str {
+        match self {
+            Self::Sqlite => "SELECT sqlite_version();",
+            #[cfg(feature = "postgres")]
+            Self::Postgres => "SELECT CAST(current_setting('server_version') AS TEXT);",
+            #[cfg(feature = "mysql")]
+            Self::MySql => "SELECT CAST(VERSION() AS CHAR);",
+        }
+    }
+
+    pub(crate) fn migrations_table_ddl(&self, table: &str) -> String {
+        match self {
+            Self::Sqlite => format!(
+                r#"
+                CREATE TABLE IF NOT EXISTS {table} (
+                    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+                    name TEXT NOT NULL,
+                    path TEXT NOT NULL,
+                    ran BOOLEAN NOT NULL,
+                    timestamp TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                    down TEXT,
+                    checksum TEXT,
+                    duration_ms BIGINT,
+                    applied_by TEXT,
+                    app_version TEXT,
+                    error TEXT,
+                    namespace TEXT
+                );
+                "#
+            ),
+            #[cfg(feature = "postgres")]
+            Self::Postgres => format!(
+                r#"
+                CREATE TABLE IF NOT EXISTS {table} (
+                    id SERIAL PRIMARY KEY,
+                    name TEXT NOT NULL,
+                    path TEXT NOT NULL,
+                    ran BOOLEAN NOT NULL,
+                    timestamp TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                    down TEXT,
+                    checksum TEXT,
+                    duration_ms BIGINT,
+                    applied_by TEXT,
+                    app_version TEXT,
+                    error TEXT,
+                    namespace TEXT
+                );
+                "#
+            ),
+            #[cfg(feature = "mysql")]
+            Self::MySql => format!(
+                r#"
+                CREATE TABLE IF NOT EXISTS {table} (
+                    id INTEGER NOT NULL PRIMARY KEY AUTO_INCREMENT,
+                    name TEXT NOT NULL,
+                    path TEXT NOT NULL,
+                    ran BOOLEAN NOT NULL,
+                    timestamp TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                    down TEXT,
+                    checksum TEXT,
+                    duration_ms BIGINT,
+                    applied_by TEXT,
+                    app_version TEXT,
+                    error TEXT,
+                    namespace TEXT
+                );
+                "#
+            ),
+        }
+    }
+
+    /// Query returning the column names of `table` as text, in `schema` or the
+    /// current one.
+    pub(crate) fn columns_query(&self, schema: Option<&str>, table: &str) -> String {
+        let literal = |value: &str| format!("'{}'", value.replace('\'', "''"));
+        let table = literal(table);
+        match self {
+            Self::Sqlite => match schema {
+                Some(schema) => format!(
+                    "SELECT name FROM pragma_table_info({table}, {});",
+                    literal(schema)
+                ),
+                None => format!("SELECT name FROM pragma_table_info({table});"),
+            },
+            #[cfg(feature = "postgres")]
+            Self::Postgres => format!(
+                "SELECT CAST(column_name AS TEXT) FROM information_schema.columns \
+                 WHERE table_schema = {} AND table_name = {table};",
+                schema.map_or("current_schema()".to_string(), literal)
+            ),
+            #[cfg(feature = "mysql")]
+            Self::MySql => format!(
+                "SELECT CAST(column_name AS CHAR) FROM information_schema.columns \
+                 WHERE table_schema = {} AND table_name = {table};",
+                schema.map_or("DATABASE()".to_string(), literal)
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backend_from_url() {
+        assert_eq!(
+            Backend::from_url("sqlite://db.sqlite"),
+            Some(Backend::Sqlite)
+        );
+        assert_eq!(Backend::from_url("sqlite::memory:"), Some(Backend::Sqlite));
+        assert_eq!(Backend::from_url("db.sqlite"), None);
+        #[cfg(feature = "postgres")]
+        assert_eq!(
+            Backend::from_url("postgres://localhost/db"),
+            Some(Backend::Postgres)
+        );
+        #[cfg(not(feature = "postgres"))]
+        assert_eq!(Backend::from_url("postgres://localhost/db"), None);
+        #[cfg(feature = "mysql")]
+        assert_eq!(
+            Backend::from_url("mariadb://localhost/db"),
+            Some(Backend::MySql)
+        );
+    }
+
+    #[test]
+    fn test_delete_batch() {
+        let condition = Backend::Sqlite.older_than("created_at", 30);
+        assert_eq!(
+            Backend::Sqlite.delete_batch("sessions", &condition, 100),
+            "DELETE FROM sessions WHERE rowid IN (SELECT rowid FROM sessions WHERE \
+             created_at < datetime('now', '-30 days') LIMIT 100);"
+        );
+    }
+
+    #[test]
+    fn test_quote_identifier() {
+        assert_eq!(
+            Backend::Sqlite.quote_identifier("migrations"),
+            "\"migrations\""
+        );
+        assert_eq!(Backend::Sqlite.quote_identifier("a\"b"), "\"a\"\"b\"");
+        #[cfg(feature = "mysql")]
+        assert_eq!(
+            Backend::MySql.quote_identifier("migrations"),
+            "`migrations`"
+        );
+    }
+
+    #[test]
+    fn test_parse_sql() {
+        assert!(Backend::Sqlite
+            .parse_sql("CREATE TABLE a (id INTEGER);\nINSERT INTO a VALUES (1);")
+            .is_ok());
+        assert!(Backend::Sqlite.parse_sql("-- only a comment\n").is_ok());
+        assert!(Backend::Sqlite
+            .parse_sql("CREATE TABL a (id INTEGER);")
+            .is_err());
+    }
+
+    #[test]
+    fn test_insert_or_ignore() {
+        assert_eq!(
+            Backend::Sqlite.insert_or_ignore("migrations_lock", "id", "1"),
+            "INSERT OR IGNORE INTO migrations_lock (id) VALUES (1);"
+        );
+        #[cfg(feature = "postgres")]
+        assert_eq!(
+            Backend::Postgres.insert_or_ignore("migrations_lock", "id", "1"),
+            "INSERT INTO migrations_lock (id) VALUES (1) ON CONFLICT DO NOTHING;"
+        );
+    }
+}
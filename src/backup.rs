@@ -0,0 +1,113 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use sqlx::AnyPool;
+use tracing::error;
+
+use super::{
+    lock::clear_lock,
+    migrations::{connect, migration_database_url, HistoryTable},
+    AromaticError, Backend,
+};
+
+/// Copies the SQLite database of `pool` next to it, as `db.sqlite.<unix time ms>.bak`.
+/// `VACUUM INTO` writes a consistent copy even while other connections use the
+/// database. Returns the path of the copy.
+pub(crate) async fn backup_sqlite(pool: &AnyPool) -> Result<PathBuf, AromaticError> {
+    let file = database_file(pool).await?;
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis())
+        .unwrap_or_default();
+    let backup = PathBuf::from(format!("{}.{millis}.bak", file.display()));
+    let path = backup.display().to_string().replace('\'', "''");
+    if let Err(err) = sqlx::query(&format!("VACUUM INTO '{path}';"))
+        .execute(pool)
+        .await
+    {
+        error!(
+            function = "backup_sqlite",
+            error_message = format!("{err}"),
+            message = format!("Could not back up {}", file.display()),
+        );
+        return Err(err.into());
+    }
+    Ok(backup)
+}
+
+/// Replaces the SQLite database of the migrations with `backup`, a copy made before
+/// a run, and removes its `-wal` and `-shm` files so nothing of the botched run is
+/// replayed on top. The copy was made while the run held the migrations lock, so it
+/// is released. Stop the application first, its connections would keep writing to
+/// the replaced file. Returns the path of the database.
+pub async fn restore_backup(backup: &Path) -> Result<PathBuf, AromaticError> {
+    if !backup.is_file() {
+        return Err(AromaticError::io(
+            backup,
+            std::io::Error::from(std::io::ErrorKind::NotFound),
+        ));
+    }
+    let (db_url, backend) = migration_database_url()?;
+    if backend != Backend::Sqlite {
+        return Err(AromaticError::Config(
+            "only SQLite databases can be restored from a backup".to_string(),
+        ));
+    }
+    // Opening it first would leave this process with locks on the file it replaces.
+    let file = sqlite_file(&db_url).ok_or_else(|| {
+        AromaticError::Config(format!("{db_url} does not point to a database file"))
+    })?;
+    for suffix in ["-wal", "-shm"] {
+        let path = PathBuf::from(format!("{}{suffix}", file.display()));
+        if path.exists() {
+            std::fs::remove_file(&path).map_err(|err| AromaticError::io(&path, err))?;
+        }
+    }
+    std::fs::copy(backup, &file).map_err(|err| AromaticError::io(&file, err))?;
+    let pool = connect(&db_url).await?;
+    clear_lock(backend, &pool, &HistoryTable::from_env()).await?;
+    Ok(file)
+}
+
+/// Path in a `sqlite://db.sqlite?mode=rwc` or `sqlite:db.sqlite` url.
+fn sqlite_file(db_url: &str) -> Option<PathBuf> {
+    let rest = db_url.strip_prefix("sqlite:")?;
+    let rest = rest.strip_prefix("//").unwrap_or(rest);
+    let path = rest.split('?').next().unwrap_or_default();
+    (!path.is_empty() && path != ":memory:").then(|| PathBuf::from(path))
+}
+
+/// File of the main database, in-memory databases don't have one.
+async fn database_file(pool: &AnyPool) -> Result<PathBuf, AromaticError> {
+    let file: String =
+        sqlx::query_scalar("SELECT file FROM pragma_database_list WHERE name = 'main';")
+            .fetch_one(pool)
+            .await?;
+    match file.is_empty() {
+        true => Err(AromaticError::Config(
+            "the database is in memory, there is no file to back up".to_string(),
+        )),
+        false => Ok(PathBuf::from(file)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sqlite_file() {
+        assert_eq!(
+            sqlite_file("sqlite:///tmp/db.sqlite?mode=rwc"),
+            Some(PathBuf::from("/tmp/db.sqlite"))
+        );
+        assert_eq!(
+            sqlite_file("sqlite://db.sqlite"),
+            Some(PathBuf::from("db.sqlite"))
+        );
+        assert_eq!(sqlite_file("sqlite::memory:"), None);
+        assert_eq!(sqlite_file("postgres://localhost/db"), None);
+    }
+}
@@ -1,13 +1,6 @@
-use clap::Parser;
-use console::style;
-use sqlx_cli::Opt;
+use aromatic::run_cli;
 
 #[tokio::main]
 async fn main() {
-    dotenvy::dotenv().ok();
-    // no special handling here
-    if let Err(error) = sqlx_cli::run(Opt::parse()).await {
-        println!("{} {}", style("error:").bold().red(), error);
-        std::process::exit(1);
-    }
+    run_cli().await;
 }
@@ -1,12 +1,46 @@
+use std::{
+    io::Write,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
 use clap::{Arg, ArgAction, ArgMatches, Command};
+use console::style;
 
-use super::migrate;
+use super::{
+    audit_column_types, baseline, check_app_privileges, cleanup,
+    config::{default_env_files, find_config_path, load_env_files, CONFIG_FILE},
+    doctor, fake, find_file_config, force_unlock, init, inspect_database, maintain,
+    migrate_to, migrate_with_shutdown, migration_history, migration_status,
+    migrations::database_url,
+    redo, resolve_database_url, restore_backup, rollback, seed, to_rust_structs,
+    validate, validate_file_config, AromaticError, Diagnosis, HistoryRecord,
+    HistoryState, Maintenance, MaintenanceTask, MigrationReport, MigrationState,
+    MigrationStatus, TextInTypedColumn, Throttle, DEFAULT_BATCH_SIZE,
+};
 
 pub async fn run_cli() {
+    // invoked as `cargo aromacli <args>` cargo passes the subcommand name first
+    let args = std::env::args()
+        .enumerate()
+        .filter_map(|(i, arg)| (i != 1 || arg != "aromacli").then_some(arg));
     let matches = Command::new("AromaCLIck")
         .version("0.1.0")
         .author("Lucas Montes <lluc23@hotmail.com>")
         .about("A CLI for handling migrations and ORM operations")
+        .arg(
+            Arg::new("init")
+                .long("init")
+                .action(ArgAction::SetTrue)
+                .help(
+                    "Create the migrations folder with a first migration, aromatic.toml \
+                     and a DATABASE_URL in .env, keeping the files that exist",
+                ),
+        )
         .arg(
             Arg::new("migrate")
                 .short('m')
@@ -15,12 +49,753 @@ pub async fn run_cli() {
                 .action(ArgAction::SetTrue)
                 .help("Run all the migrations"),
         )
-        .get_matches();
-    handle_cli(matches).await;
+        .arg(
+            Arg::new("migrate-to")
+                .long("migrate-to")
+                .value_name("MIGRATION")
+                .help(
+                    "Apply the migrations up to MIGRATION and revert the applied ones \
+                     after it",
+                ),
+        )
+        .arg(
+            Arg::new("validate")
+                .long("validate")
+                .action(ArgAction::SetTrue)
+                .help(
+                    "Parse the pending migrations without running them, before \
+                     --migrate when both are given",
+                ),
+        )
+        .arg(
+            Arg::new("report")
+                .long("report")
+                .value_name("FORMAT")
+                .value_parser(["json"])
+                .help(
+                    "Print what --migrate or --migrate-to did as JSON, with the \
+                     checksums and durations of the applied migrations",
+                ),
+        )
+        .arg(
+            Arg::new("baseline")
+                .long("baseline")
+                .value_name("MIGRATION")
+                .help(
+                    "Record the migrations up to MIGRATION as applied without running \
+                     them, for a database that already has their schema",
+                ),
+        )
+        .arg(
+            Arg::new("fake")
+                .long("fake")
+                .value_name("MIGRATION")
+                .action(ArgAction::Append)
+                .help(
+                    "Record MIGRATION as applied without running it, for a change made \
+                     by hand. Can be repeated",
+                ),
+        )
+        .arg(
+            Arg::new("dry-run")
+                .long("dry-run")
+                .action(ArgAction::SetTrue)
+                .help("Show the migrations --migrate would run and their SQL, without committing"),
+        )
+        .arg(
+            Arg::new("integrity-checks")
+                .long("integrity-checks")
+                .value_name("MODE")
+                .value_parser(["fail", "rollback"])
+                .help(
+                    "Check the SQLite database for corruption and broken references \
+                     once migrated, or before committing each migration to roll it back",
+                ),
+        )
+        .arg(
+            Arg::new("backup")
+                .long("backup")
+                .action(ArgAction::SetTrue)
+                .help("Copy the SQLite database before --migrate applies anything"),
+        )
+        .arg(
+            Arg::new("restore-backup")
+                .long("restore-backup")
+                .value_name("FILE")
+                .value_parser(clap::value_parser!(PathBuf))
+                .help("Replace the SQLite database with a copy made by --backup"),
+        )
+        .arg(
+            Arg::new("single-transaction")
+                .long("single-transaction")
+                .action(ArgAction::SetTrue)
+                .help("Run all the pending migrations in one transaction instead of one per file"),
+        )
+        .arg(
+            Arg::new("allow-unversioned")
+                .long("allow-unversioned")
+                .action(ArgAction::SetTrue)
+                .help("Run migrations without a numeric or timestamp prefix, last"),
+        )
+        .arg(
+            Arg::new("repair")
+                .long("repair")
+                .action(ArgAction::SetTrue)
+                .help("Accept applied migrations whose file changed, storing the new checksum"),
+        )
+        .arg(
+            Arg::new("force-unlock")
+                .long("force-unlock")
+                .action(ArgAction::SetTrue)
+                .help("Release the migrations lock left by a runner that was killed"),
+        )
+        .arg(
+            Arg::new("status")
+                .long("status")
+                .action(ArgAction::SetTrue)
+                .help("Show which migrations are applied, pending or missing on disk"),
+        )
+        .arg(
+            Arg::new("json")
+                .long("json")
+                .action(ArgAction::SetTrue)
+                .help("Print --status and --history as JSON"),
+        )
+        .arg(
+            Arg::new("history")
+                .long("history")
+                .action(ArgAction::SetTrue)
+                .help("Show the migrations history with durations, checksums and errors"),
+        )
+        .arg(
+            Arg::new("last")
+                .long("last")
+                .value_name("COUNT")
+                .value_parser(clap::value_parser!(usize))
+                .help("Only show the last COUNT entries of --history"),
+        )
+        .arg(
+            Arg::new("failed-only")
+                .long("failed-only")
+                .action(ArgAction::SetTrue)
+                .help("Only show the migrations of --history whose last run failed"),
+        )
+        .arg(
+            Arg::new("rollback")
+                .short('r')
+                .long("rollback")
+                .value_name("STEPS")
+                .value_parser(clap::value_parser!(u32))
+                .help("Revert the last STEPS applied migrations"),
+        )
+        .arg(
+            Arg::new("redo")
+                .long("redo")
+                .value_name("STEPS")
+                .value_parser(clap::value_parser!(u32))
+                .help("Revert the last STEPS applied migrations and apply them again"),
+        )
+        .arg(
+            Arg::new("seed")
+                .long("seed")
+                .action(ArgAction::SetTrue)
+                .help("Run the seeds of the seeds folder that didn't run yet"),
+        )
+        .arg(
+            Arg::new("env")
+                .long("env")
+                .value_name("ENV")
+                .help("Also run the seeds of seeds/ENV, defaults to SEED_ENV"),
+        )
+        .arg(
+            Arg::new("cleanup")
+                .long("cleanup")
+                .action(ArgAction::SetTrue)
+                .help("Delete the rows older than the [[retention]] policies of aromatic.toml"),
+        )
+        .arg(
+            Arg::new("cleanup-sql")
+                .long("cleanup-sql")
+                .action(ArgAction::SetTrue)
+                .help("Print the statements --cleanup runs, to schedule them elsewhere"),
+        )
+        .arg(
+            Arg::new("batch-size")
+                .long("batch-size")
+                .value_name("ROWS")
+                .value_parser(clap::value_parser!(u32).range(1..))
+                .default_value("1000")
+                .help("Rows deleted per statement by --cleanup"),
+        )
+        .arg(
+            Arg::new("max-rows-per-sec")
+                .long("max-rows-per-sec")
+                .value_name("ROWS")
+                .value_parser(clap::value_parser!(u32).range(1..))
+                .help("Slow --cleanup down to at most this many deleted rows per second"),
+        )
+        .arg(
+            Arg::new("batch-pause")
+                .long("batch-pause")
+                .value_name("MS")
+                .value_parser(clap::value_parser!(u64))
+                .help("Milliseconds --cleanup waits between two batches"),
+        )
+        .arg(
+            Arg::new("maintain")
+                .long("maintain")
+                .value_name("TASKS")
+                .value_parser(["checkpoint", "vacuum", "analyze", "integrity-check", "all"])
+                .num_args(0..)
+                .value_delimiter(',')
+                .default_missing_value("all")
+                .help(
+                    "Run SQLite maintenance tasks, comma separated, all of them when \
+                     none is given",
+                ),
+        )
+        .arg(
+            Arg::new("migrations-table")
+                .long("migrations-table")
+                .value_name("TABLE")
+                .help(
+                    "Table keeping the migrations history, SCHEMA.TABLE to put it in \
+                     another schema. Defaults to MIGRATIONS_TABLE or migrations",
+                ),
+        )
+        .arg(
+            Arg::new("doctor")
+                .long("doctor")
+                .action(ArgAction::SetTrue)
+                .help("Show the database version and which features it supports"),
+        )
+        .arg(
+            Arg::new("inspectdb")
+                .long("inspectdb")
+                .action(ArgAction::SetTrue)
+                .help("Print FromRow structs for the tables of the database"),
+        )
+        .arg(
+            Arg::new("audit-types")
+                .long("audit-types")
+                .action(ArgAction::SetTrue)
+                .help(
+                    "Print a migration fixing the boolean and integer columns holding \
+                     text values",
+                ),
+        )
+        .arg(
+            Arg::new("database-url")
+                .long("database-url")
+                .value_name("URL")
+                .help("Database to use, takes precedence over DATABASE_URL"),
+        )
+        .arg(
+            Arg::new("migration-database-url")
+                .long("migration-database-url")
+                .value_name("URL")
+                .help(
+                    "Database to run migrations on, with a role allowed to change the \
+                     schema. Takes precedence over MIGRATION_DATABASE_URL",
+                ),
+        )
+        .arg(
+            Arg::new("check-privileges")
+                .long("check-privileges")
+                .action(ArgAction::SetTrue)
+                .help("Fail if the user of DATABASE_URL is allowed to change the schema"),
+        )
+        .arg(
+            Arg::new("print-config")
+                .long("print-config")
+                .action(ArgAction::SetTrue)
+                .help("Show the resolved configuration and where it comes from"),
+        )
+        .arg(
+            Arg::new("validate-config")
+                .long("validate-config")
+                .action(ArgAction::SetTrue)
+                .help("Check aromatic.toml for unknown keys and values of the wrong type"),
+        )
+        .arg(
+            Arg::new("env-file")
+                .long("env-file")
+                .value_name("PATH")
+                .value_parser(clap::value_parser!(PathBuf))
+                .action(ArgAction::Append)
+                .help(
+                    "Env file to load, can be repeated and later files override earlier \
+                     ones. Defaults to .env, .env.local and .env.PROFILE",
+                ),
+        )
+        .arg(
+            Arg::new("profile")
+                .long("profile")
+                .value_name("PROFILE")
+                .help(
+                    "Also load .env.PROFILE when no env file is given, defaults to \
+                     AROMATIC_PROFILE",
+                ),
+        )
+        .get_matches_from(args);
+    if let Err(error) = handle_cli(matches).await {
+        eprintln!("{} {}", style("error:").bold().red(), error);
+        std::process::exit(1);
+    }
 }
 
-async fn handle_cli(matches: ArgMatches) {
+async fn handle_cli(matches: ArgMatches) -> Result<(), AromaticError> {
+    if *matches.get_one("init").unwrap_or(&false) {
+        for path in init(&std::env::current_dir().unwrap_or_default())? {
+            println!("{} {}", style("created:").bold().green(), path.display());
+        }
+    }
+    let env_files = match matches.get_many::<PathBuf>("env-file") {
+        Some(files) => files.cloned().collect(),
+        None => {
+            let profile = matches
+                .get_one::<String>("profile")
+                .cloned()
+                .or_else(|| std::env::var("AROMATIC_PROFILE").ok());
+            default_env_files(profile.as_deref())
+        },
+    };
+    load_env_files(&env_files);
+    let database_url = matches.get_one::<String>("database-url");
+    if let Some(url) = database_url {
+        std::env::set_var("DATABASE_URL", url);
+    }
+    if let Some(url) = matches.get_one::<String>("migration-database-url") {
+        std::env::set_var("MIGRATION_DATABASE_URL", url);
+    }
+    if let Some(table) = matches.get_one::<String>("migrations-table") {
+        std::env::set_var("MIGRATIONS_TABLE", table);
+    }
+    if let Some(env) = matches.get_one::<String>("env") {
+        std::env::set_var("SEED_ENV", env);
+    }
+    if *matches.get_one("allow-unversioned").unwrap_or(&false) {
+        std::env::set_var("ALLOW_UNVERSIONED_MIGRATIONS", "true");
+    }
+    let dry_run = *matches.get_one("dry-run").unwrap_or(&false);
+    if dry_run {
+        std::env::set_var("DRY_RUN_MIGRATIONS", "true");
+    }
+    if let Some(mode) = matches.get_one::<String>("integrity-checks") {
+        std::env::set_var("MIGRATION_INTEGRITY_CHECKS", mode);
+    }
+    if *matches.get_one("backup").unwrap_or(&false) {
+        std::env::set_var("BACKUP_BEFORE_MIGRATIONS", "true");
+    }
+    if *matches.get_one("single-transaction").unwrap_or(&false) {
+        std::env::set_var("SINGLE_TRANSACTION_MIGRATIONS", "true");
+    }
+    if *matches.get_one("repair").unwrap_or(&false) {
+        std::env::set_var("REPAIR_MIGRATION_CHECKSUMS", "true");
+    }
+    if *matches.get_one("validate-config").unwrap_or(&false) {
+        match find_config_path() {
+            Some(path) => {
+                validate_file_config(&path)?;
+                println!("{} {}", style("valid:").bold().green(), path.display());
+            },
+            None => println!("no {CONFIG_FILE} found"),
+        }
+    }
+    if *matches.get_one("print-config").unwrap_or(&false) {
+        print_config(database_url.map(String::as_str));
+    }
+    if *matches.get_one("check-privileges").unwrap_or(&false) {
+        check_app_privileges().await?;
+        println!(
+            "{} the application user can't change the schema",
+            style("ok:").bold().green()
+        );
+    }
+    if let Some(backup) = matches.get_one::<PathBuf>("restore-backup") {
+        let database = restore_backup(backup).await?;
+        println!(
+            "{} {} from {}",
+            style("restored:").bold().green(),
+            database.display(),
+            backup.display()
+        );
+    }
+    if *matches.get_one("doctor").unwrap_or(&false) {
+        print_diagnosis(doctor().await?);
+    }
+    if *matches.get_one("inspectdb").unwrap_or(&false) {
+        print!("{}", to_rust_structs(&inspect_database().await?));
+    }
+    if *matches.get_one("audit-types").unwrap_or(&false) {
+        print_column_types_fix(audit_column_types().await?)?;
+    }
+    if *matches.get_one("force-unlock").unwrap_or(&false) {
+        match force_unlock().await? {
+            true => println!(
+                "{} migrations lock released",
+                style("unlocked:").bold().green()
+            ),
+            false => println!("migrations were not locked"),
+        }
+    }
+    if let Some(up_to) = matches.get_one::<String>("baseline") {
+        for name in baseline("migrations/sqlite", up_to).await? {
+            println!("{} {}", style("baselined:").bold().green(), name);
+        }
+    }
+    if let Some(names) = matches.get_many::<String>("fake") {
+        let names: Vec<&str> = names.map(String::as_str).collect();
+        for name in fake("migrations/sqlite", &names).await? {
+            println!("{} {}", style("faked:").bold().green(), name);
+        }
+    }
+    if *matches.get_one("status").unwrap_or(&false) {
+        let statuses = migration_status("migrations/sqlite").await?;
+        match *matches.get_one("json").unwrap_or(&false) {
+            true => println!(
+                "{}",
+                serde_json::to_string_pretty(&statuses).unwrap_or_default()
+            ),
+            false => print_status(&statuses),
+        }
+    }
+    if *matches.get_one("history").unwrap_or(&false) {
+        let mut records = migration_history().await?;
+        if *matches.get_one("failed-only").unwrap_or(&false) {
+            records.retain(|record| record.state == HistoryState::Failed);
+        }
+        if let Some(last) = matches.get_one::<usize>("last") {
+            records.drain(..records.len().saturating_sub(*last));
+        }
+        match *matches.get_one("json").unwrap_or(&false) {
+            true => println!(
+                "{}",
+                serde_json::to_string_pretty(&records).unwrap_or_default()
+            ),
+            false => print_history(&records),
+        }
+    }
+    if *matches.get_one("validate").unwrap_or(&false) {
+        for name in validate("migrations/sqlite").await? {
+            println!("{} {}", style("valid:").bold().green(), name);
+        }
+    }
     if *matches.get_one("migrate").unwrap_or(&false) {
-        migrate("migrations/sqlite").await;
+        let shutdown = Arc::new(AtomicBool::new(false));
+        tokio::spawn(listen_for_shutdown(shutdown.clone()));
+        let report = migrate_with_shutdown("migrations/sqlite", &shutdown).await;
+        if matches.contains_id("report") {
+            print_report_json(report)?;
+        } else {
+            print_migrate_report(report?, dry_run);
+        }
+    }
+    if let Some(target) = matches.get_one::<String>("migrate-to") {
+        let report = migrate_to("migrations/sqlite", target).await;
+        if matches.contains_id("report") {
+            print_report_json(report)?;
+        } else {
+            let report = report?;
+            for name in report.reverted {
+                println!("{} {}", style("reverted:").bold().green(), name);
+            }
+            for name in report.applied {
+                println!("{} {}", style("applied:").bold().green(), name);
+            }
+        }
+    }
+    if let Some(steps) = matches.get_one::<u32>("rollback") {
+        for name in rollback("migrations/sqlite", *steps).await? {
+            println!("{} {}", style("reverted:").bold().green(), name);
+        }
+    }
+    if let Some(steps) = matches.get_one::<u32>("redo") {
+        let report = redo("migrations/sqlite", *steps).await?;
+        for name in report.reverted {
+            println!("{} {}", style("reverted:").bold().green(), name);
+        }
+        for name in report.applied {
+            println!("{} {}", style("applied:").bold().green(), name);
+        }
     }
+    if *matches.get_one("seed").unwrap_or(&false) {
+        for name in seed("seeds").await? {
+            println!("{} {}", style("seeded:").bold().green(), name);
+        }
+    }
+    if let Some(names) = matches.get_many::<String>("maintain") {
+        let names: Vec<&String> = names.collect();
+        let tasks: Vec<MaintenanceTask> = match names.iter().any(|name| *name == "all") {
+            true => MaintenanceTask::ALL.to_vec(),
+            false => names
+                .into_iter()
+                .filter_map(|name| MaintenanceTask::from_name(name))
+                .collect(),
+        };
+        print_maintenance(&maintain(&tasks).await?);
+    }
+    let cleanup = *matches.get_one("cleanup").unwrap_or(&false);
+    let cleanup_sql = *matches.get_one("cleanup-sql").unwrap_or(&false);
+    if cleanup || cleanup_sql {
+        let batch_size = *matches
+            .get_one::<u32>("batch-size")
+            .unwrap_or(&DEFAULT_BATCH_SIZE);
+        let throttle = Throttle {
+            max_rows_per_second: matches.get_one::<u32>("max-rows-per-sec").copied(),
+            pause: Duration::from_millis(
+                matches.get_one::<u64>("batch-pause").copied().unwrap_or(0),
+            ),
+        };
+        run_cleanup(cleanup_sql, batch_size, throttle).await?;
+    }
+    Ok(())
+}
+
+async fn run_cleanup(
+    print_sql: bool,
+    batch_size: u32,
+    throttle: Throttle,
+) -> Result<(), AromaticError> {
+    let policies = find_file_config()?
+        .map(|(_, config)| config.retention)
+        .unwrap_or_default();
+    if policies.is_empty() {
+        println!("no [[retention]] policies in {CONFIG_FILE}");
+        return Ok(());
+    }
+    if print_sql {
+        let (_, backend) = database_url()?;
+        for policy in &policies {
+            println!("{}", policy.cleanup_sql(backend, batch_size));
+        }
+        return Ok(());
+    }
+    let totals = cleanup(&policies, batch_size, throttle, |policy, deleted| {
+        print!(
+            "\r{} {} rows from {}",
+            style("deleting:").bold(),
+            deleted,
+            policy.table
+        );
+        let _ = std::io::stdout().flush();
+    })
+    .await;
+    println!();
+    for (policy, total) in policies.iter().zip(totals?) {
+        println!(
+            "{} {} rows from {}",
+            style("deleted:").bold().green(),
+            total,
+            policy.table
+        );
+    }
+    Ok(())
+}
+
+/// The fix-up migration goes to stdout so it can be redirected into the migrations
+/// folder, the summary is made of SQL comments.
+fn print_column_types_fix(found: Vec<TextInTypedColumn>) -> Result<(), AromaticError> {
+    if found.is_empty() {
+        eprintln!("no text values in boolean or integer columns");
+        return Ok(());
+    }
+    let (_, backend) = database_url()?;
+    for column in &found {
+        println!(
+            "-- {}.{} ({}): {} text values",
+            column.table, column.column, column.declared_type, column.rows
+        );
+    }
+    for column in &found {
+        println!("{}", column.fix_sql(backend));
+    }
+    Ok(())
+}
+
+fn print_config(database_url: Option<&str>) {
+    match resolve_database_url(database_url) {
+        Ok(Some(resolved)) => println!(
+            "DATABASE_URL = {} {}",
+            style(resolved.value).bold(),
+            style(format!("(from {})", resolved.source)).dim(),
+        ),
+        Ok(None) => println!("DATABASE_URL {}", style("is not set").red()),
+        Err(err) => println!("DATABASE_URL {}", style(err).red()),
+    }
+}
+
+fn print_maintenance(done: &[Maintenance]) {
+    for maintenance in done {
+        let healthy = maintenance.task != MaintenanceTask::IntegrityCheck
+            || maintenance.output == ["ok"];
+        let label = match healthy {
+            true => style(format!("{}:", maintenance.task.name()))
+                .bold()
+                .green(),
+            false => style(format!("{}:", maintenance.task.name())).bold().red(),
+        };
+        println!(
+            "{} {} {}",
+            label,
+            match maintenance.output.is_empty() {
+                true => "done".to_string(),
+                false => maintenance.output.join(", "),
+            },
+            style(format!("({} ms)", maintenance.duration.as_millis())).dim(),
+        );
+    }
+}
+
+fn print_status(statuses: &[MigrationStatus]) {
+    let width = statuses.iter().map(|s| s.name.len()).max().unwrap_or(0);
+    for status in statuses {
+        let state = match status.state {
+            MigrationState::Applied => style("applied").green(),
+            MigrationState::Pending => style("pending").yellow(),
+            MigrationState::MissingOnDisk => style("missing on disk").red(),
+        };
+        println!(
+            "{:width$}  {:15}  {}",
+            status.name,
+            state,
+            style(status.applied_at.as_deref().unwrap_or_default()).dim(),
+        );
+    }
+}
+
+fn print_migrate_report(report: MigrationReport, dry_run: bool) {
+    if let Some(backup) = &report.backup {
+        println!("{} {}", style("backed up:").bold(), backup.display());
+    }
+    if dry_run {
+        for (name, sql) in report.applied.iter().zip(&report.sql) {
+            println!("{} {}", style("would apply:").bold().cyan(), name);
+            println!("{}", style(sql.trim()).dim());
+        }
+    } else {
+        for name in report.applied {
+            println!("{} {}", style("applied:").bold().green(), name);
+        }
+    }
+    if let Some(next) = report.interrupted_before {
+        println!(
+            "{} run interrupted, {} and the following migrations are still pending",
+            style("warning:").bold().yellow(),
+            style(next).bold(),
+        );
+    }
+}
+
+/// Prints the report, or the migration that failed along with the error, before
+/// returning the error.
+fn print_report_json(
+    report: Result<MigrationReport, AromaticError>,
+) -> Result<(), AromaticError> {
+    let json = match &report {
+        Ok(report) => serde_json::to_value(report).unwrap_or_default(),
+        Err(AromaticError::Migration {
+            name,
+            location,
+            source,
+            ..
+        }) => serde_json::json!({
+            "failed": name,
+            "line": location.as_ref().map(|location| location.line),
+            "statement": location.as_ref().map(|location| &location.statement),
+            "error": source.to_string(),
+        }),
+        Err(err) => serde_json::json!({ "error": err.to_string() }),
+    };
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&json).unwrap_or_default()
+    );
+    report.map(|_| ())
+}
+
+fn print_history(records: &[HistoryRecord]) {
+    let width = records.iter().map(|r| r.name.len()).max().unwrap_or(0);
+    for record in records {
+        let state = match record.state {
+            HistoryState::Applied => style("applied").green(),
+            HistoryState::Reverted => style("reverted").yellow(),
+            HistoryState::Failed => style("failed").red(),
+        };
+        let duration = record
+            .duration_ms
+            .map(|ms| format!("{ms} ms"))
+            .unwrap_or_default();
+        let checksum = record.checksum.as_deref().unwrap_or_default();
+        println!(
+            "{:width$}  {:8}  {}  {:>9}  {}  {}",
+            record.name,
+            state,
+            style(&record.applied_at).dim(),
+            duration,
+            style(&checksum[..checksum.len().min(12)]).dim(),
+            record.applied_by.as_deref().unwrap_or_default(),
+        );
+        if let Some(error) = &record.error {
+            println!("{:width$}  {}", "", style(error).red());
+        }
+    }
+}
+
+fn print_diagnosis(diagnosis: Diagnosis) {
+    println!(
+        "{:?} {}",
+        diagnosis.backend,
+        style(diagnosis.version).bold()
+    );
+    for feature in diagnosis.features {
+        let status = match feature.available {
+            true => style("available:").bold().green(),
+            false => style("unavailable:").bold().red(),
+        };
+        println!(
+            "{} {} {}",
+            status,
+            feature.name,
+            style(format!("(since {})", feature.since)).dim(),
+        );
+    }
+}
+
+async fn listen_for_shutdown(shutdown: Arc<AtomicBool>) {
+    wait_for_signal().await;
+    shutdown.store(true, Ordering::SeqCst);
+    println!(
+        "{} shutdown requested, finishing the current migration",
+        style("warning:").bold().yellow(),
+    );
+    // a second signal stops right away, the database rolls back the open transaction
+    wait_for_signal().await;
+    println!(
+        "{} stopping now, the current migration is rolled back by the database",
+        style("warning:").bold().yellow(),
+    );
+    std::process::exit(130);
+}
+
+#[cfg(unix)]
+async fn wait_for_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut terminate = match signal(SignalKind::terminate()) {
+        Ok(s) => s,
+        Err(_) => {
+            let _ = tokio::signal::ctrl_c().await;
+            return;
+        },
+    };
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => (),
+        _ = terminate.recv() => (),
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_signal() {
+    let _ = tokio::signal::ctrl_c().await;
 }
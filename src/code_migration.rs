@@ -0,0 +1,54 @@
+use std::{fmt, future::Future, pin::Pin};
+
+use sqlx::AnyConnection;
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Migration written in Rust, for backfills and data transformations that are
+/// awkward in SQL. Registered with [`MigratorBuilder::migration`], it is ordered with
+/// the SQL files by the version prefix of its name and recorded in the same history.
+/// `up` and `down` get the connection of the migration's transaction.
+///
+/// ```ignore
+/// struct Backfill;
+///
+/// impl Migration for Backfill {
+///     fn name(&self) -> &str {
+///         "0003_backfill_slugs"
+///     }
+///
+///     fn up<'a>(&'a self, connection: &'a mut AnyConnection) -> BoxFuture<'a, sqlx::Result<()>> {
+///         Box::pin(async move {
+///             sqlx::query("UPDATE posts SET slug = lower(title);")
+///                 .execute(connection)
+///                 .await?;
+///             Ok(())
+///         })
+///     }
+/// }
+/// ```
+///
+/// [`MigratorBuilder::migration`]: crate::MigratorBuilder::migration
+pub trait Migration: Send + Sync {
+    /// Like a file name, `0003_backfill_slugs`.
+    fn name(&self) -> &str;
+
+    fn up<'a>(
+        &'a self,
+        connection: &'a mut AnyConnection,
+    ) -> BoxFuture<'a, sqlx::Result<()>>;
+
+    /// Reverts `up`, migrations without one can't be rolled back.
+    fn down<'a>(
+        &'a self,
+        _connection: &'a mut AnyConnection,
+    ) -> Option<BoxFuture<'a, sqlx::Result<()>>> {
+        None
+    }
+}
+
+impl fmt::Debug for dyn Migration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Migration({})", self.name())
+    }
+}
@@ -0,0 +1,112 @@
+use sqlx::AnyPool;
+
+use super::{
+    migrations::{connect, database_url},
+    AromaticError, Backend,
+};
+
+/// A column declared `BOOLEAN` or `INTEGER` holding values stored as text, like the
+/// `'true'` and `'false'` left by inserts built from strings. Only SQLite lets a
+/// typed column keep them, the other databases convert or reject them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextInTypedColumn {
+    pub table: String,
+    pub column: String,
+    pub declared_type: String,
+    /// Rows whose value is text.
+    pub rows: i64,
+}
+
+impl TextInTypedColumn {
+    /// Statement turning the textual booleans of the column into `1` and `0`, other
+    /// text values are left for a human to look at.
+    pub fn fix_sql(&self, backend: Backend) -> String {
+        let table = backend.quote_identifier(&self.table);
+        let column = backend.quote_identifier(&self.column);
+        format!(
+            "UPDATE {table} SET {column} = CASE WHEN lower(trim({column})) IN \
+             ('true', 't', 'yes') THEN 1 ELSE 0 END WHERE typeof({column}) = 'text' \
+             AND lower(trim({column})) IN ('true', 't', 'yes', 'false', 'f', 'no');"
+        )
+    }
+}
+
+/// Scans the columns declared boolean or integer of the database for values stored
+/// as text. See [`TextInTypedColumn::fix_sql`] for the migration fixing them.
+pub async fn audit_column_types() -> Result<Vec<TextInTypedColumn>, AromaticError> {
+    let (db_url, backend) = database_url()?;
+    if backend != Backend::Sqlite {
+        return Ok(Vec::new());
+    }
+    let pool = connect(&db_url).await?;
+    Ok(text_in_typed_columns(&pool).await?)
+}
+
+async fn text_in_typed_columns(
+    pool: &AnyPool,
+) -> Result<Vec<TextInTypedColumn>, sqlx::Error> {
+    let columns: Vec<(String, String, String)> = sqlx::query_as(
+        "SELECT t.name, c.name, c.type FROM sqlite_master AS t, \
+         pragma_table_info(t.name) AS c \
+         WHERE t.type = 'table' AND t.name NOT LIKE 'sqlite_%' \
+         AND (upper(c.type) LIKE '%INT%' OR upper(c.type) LIKE '%BOOL%') \
+         ORDER BY t.name, c.cid;",
+    )
+    .fetch_all(pool)
+    .await?;
+    let mut found = Vec::new();
+    for (table, column, declared_type) in columns {
+        let quoted = Backend::Sqlite.quote_identifier(&column);
+        let rows: i64 = sqlx::query_scalar(&format!(
+            "SELECT COUNT(*) FROM {} WHERE typeof({quoted}) = 'text';",
+            Backend::Sqlite.quote_identifier(&table)
+        ))
+        .fetch_one(pool)
+        .await?;
+        if rows > 0 {
+            found.push(TextInTypedColumn {
+                table,
+                column,
+                declared_type,
+                rows,
+            });
+        }
+    }
+    Ok(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TestDb;
+
+    #[tokio::test]
+    async fn test_text_in_typed_columns() {
+        let db = TestDb::new(
+            "CREATE TABLE users (id INTEGER, active BOOLEAN, name TEXT);
+             INSERT INTO users VALUES (1, 'true', 'a'), (2, 'False', 'b'), (3, 1, 'c');",
+        )
+        .await
+        .unwrap();
+        let found = text_in_typed_columns(db.pool()).await.unwrap();
+        assert_eq!(
+            found,
+            [TextInTypedColumn {
+                table: "users".to_string(),
+                column: "active".to_string(),
+                declared_type: "BOOLEAN".to_string(),
+                rows: 2,
+            }]
+        );
+
+        db.execute(&found[0].fix_sql(Backend::Sqlite))
+            .await
+            .unwrap();
+        db.assert_rows(
+            "SELECT typeof(active), CAST(active AS TEXT) FROM users ORDER BY id;",
+            &[&["integer", "1"], &["integer", "0"], &["integer", "1"]],
+        )
+        .await;
+        assert!(text_in_typed_columns(db.pool()).await.unwrap().is_empty());
+    }
+}
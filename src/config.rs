@@ -0,0 +1,379 @@
+use std::{
+    collections::HashSet,
+    fmt,
+    fs::read_to_string,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use super::AromaticError;
+
+pub const CONFIG_FILE: &str = "aromatic.toml";
+const DATABASE_URL: &str = "DATABASE_URL";
+
+/// Settings read from `aromatic.toml`.
+#[derive(Debug, Default, Deserialize)]
+pub struct FileConfig {
+    pub database_url: Option<String>,
+    /// Connection used to run migrations, with a role allowed to change the schema
+    /// while `database_url` keeps the one of the application.
+    pub migration_database_url: Option<String>,
+    #[serde(default)]
+    pub retention: Vec<RetentionPolicy>,
+}
+
+/// Rows of `table` older than `max_age_days`, going by `column`, are deleted by
+/// `--cleanup`. Declared as `[[retention]]` entries in `aromatic.toml`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct RetentionPolicy {
+    pub table: String,
+    pub column: String,
+    pub max_age_days: u32,
+}
+
+impl FileConfig {
+    const KEYS: [&'static str; 3] =
+        ["database_url", "retention", "migration_database_url"];
+
+    /// Reads the config file at `path`, the error lists every unknown key or points to
+    /// the value that doesn't have the expected type.
+    fn load(path: &Path) -> Result<Self, AromaticError> {
+        let content = read_to_string(path).map_err(|err| AromaticError::io(path, err))?;
+        Self::parse(&content).map_err(|message| AromaticError::InvalidConfig {
+            path: path.to_path_buf(),
+            message,
+        })
+    }
+
+    /// Rejects unknown keys, suggesting the closest known one, before deserializing
+    /// so typos don't silently fall back to the defaults.
+    fn parse(content: &str) -> Result<Self, String> {
+        let table: toml::Table = content.parse().map_err(|err| format!("{err}"))?;
+        let mut problems = unknown_keys(&table, &Self::KEYS, "");
+        if let Some(toml::Value::Array(policies)) = table.get("retention") {
+            for (i, policy) in policies.iter().enumerate() {
+                if let toml::Value::Table(policy) = policy {
+                    let prefix = format!("retention[{i}].");
+                    problems.extend(unknown_keys(
+                        policy,
+                        &RetentionPolicy::KEYS,
+                        &prefix,
+                    ));
+                }
+            }
+        }
+        if !problems.is_empty() {
+            return Err(problems.join("\n"));
+        }
+        toml::from_str(content).map_err(|err| format!("{err}"))
+    }
+}
+
+impl RetentionPolicy {
+    const KEYS: [&'static str; 3] = ["table", "column", "max_age_days"];
+}
+
+fn unknown_keys(table: &toml::Table, known: &[&str], prefix: &str) -> Vec<String> {
+    table
+        .keys()
+        .filter(|key| !known.contains(&key.as_str()))
+        .map(|key| match closest(key, known) {
+            Some(suggestion) => {
+                format!(
+                    "unknown key `{prefix}{key}`, did you mean `{prefix}{suggestion}`?"
+                )
+            },
+            None => format!(
+                "unknown key `{prefix}{key}`, expected one of {}",
+                known.join(", ")
+            ),
+        })
+        .collect()
+}
+
+/// Known key within a few typos of `key`, or starting like it.
+fn closest<'a>(key: &str, known: &[&'a str]) -> Option<&'a str> {
+    known
+        .iter()
+        .map(|candidate| (edit_distance(key, candidate), *candidate))
+        .filter(|(distance, candidate)| *distance <= 3 || candidate.starts_with(key))
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// The closest `aromatic.toml` in the current directory or any of its parents, an
+/// invalid one is an error rather than being skipped.
+pub fn find_file_config() -> Result<Option<(PathBuf, FileConfig)>, AromaticError> {
+    let Some(path) = find_config_path() else {
+        return Ok(None);
+    };
+    let config = FileConfig::load(&path)?;
+    Ok(Some((path, config)))
+}
+
+pub(crate) fn find_config_path() -> Option<PathBuf> {
+    let current_dir = std::env::current_dir().unwrap_or_default();
+    current_dir
+        .ancestors()
+        .map(|dir| dir.join(CONFIG_FILE))
+        .find(|path| path.is_file())
+}
+
+/// Reads the config file at `path`, the error lists every unknown key or points to
+/// the value that doesn't have the expected type.
+pub fn validate_file_config(path: &Path) -> Result<FileConfig, AromaticError> {
+    FileConfig::load(path)
+}
+
+/// Where a setting was found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigSource {
+    Flag,
+    Env,
+    ConfigFile(PathBuf),
+    DotEnv(PathBuf),
+}
+
+impl fmt::Display for ConfigSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Flag => write!(f, "command line flag"),
+            Self::Env => write!(f, "environment variable"),
+            Self::ConfigFile(path) | Self::DotEnv(path) => {
+                write!(f, "{}", path.display())
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Resolved {
+    pub value: String,
+    pub source: ConfigSource,
+}
+
+/// Finds the database url, in order: the `flag` given on the command line, the
+/// `DATABASE_URL` environment variable, `aromatic.toml` and `.env` files in the
+/// current directory or any of its parents. An invalid `aromatic.toml` on the way is
+/// an error.
+pub fn resolve_database_url(
+    flag: Option<&str>,
+) -> Result<Option<Resolved>, AromaticError> {
+    let current_dir = std::env::current_dir().unwrap_or_default();
+    resolve(flag, std::env::var(DATABASE_URL).ok(), &current_dir)
+}
+
+fn resolve(
+    flag: Option<&str>,
+    env: Option<String>,
+    start: &Path,
+) -> Result<Option<Resolved>, AromaticError> {
+    if let Some(value) = flag {
+        return Ok(Some(Resolved {
+            value: value.to_string(),
+            source: ConfigSource::Flag,
+        }));
+    }
+    if let Some(value) = env.filter(|v| !v.is_empty()) {
+        return Ok(Some(Resolved {
+            value,
+            source: ConfigSource::Env,
+        }));
+    }
+    for dir in start.ancestors() {
+        let path = dir.join(CONFIG_FILE);
+        if !path.is_file() {
+            continue;
+        }
+        if let Some(value) = FileConfig::load(&path)?.database_url {
+            return Ok(Some(Resolved {
+                value,
+                source: ConfigSource::ConfigFile(path),
+            }));
+        }
+    }
+    Ok(start.ancestors().find_map(|dir| {
+        let path = dir.join(".env");
+        let value = read_dotenv_var(&path, DATABASE_URL)?;
+        Some(Resolved {
+            value,
+            source: ConfigSource::DotEnv(path),
+        })
+    }))
+}
+
+/// Env files loaded when none are given explicitly: `.env`, `.env.local` and, when a
+/// profile is set, `.env.{profile}`. Later files override earlier ones.
+pub fn default_env_files(profile: Option<&str>) -> Vec<PathBuf> {
+    let mut files = vec![PathBuf::from(".env"), PathBuf::from(".env.local")];
+    if let Some(profile) = profile {
+        files.push(PathBuf::from(format!(".env.{profile}")));
+    }
+    files
+}
+
+/// Loads `files` in order into the process environment, missing files are skipped.
+/// Variables set before calling this are never overridden, between files the last
+/// one to define a variable wins.
+pub fn load_env_files(files: &[PathBuf]) {
+    let preset: HashSet<String> = std::env::vars().map(|(key, _)| key).collect();
+    for file in files {
+        let Ok(content) = read_to_string(file) else {
+            continue;
+        };
+        for (key, value) in parse_dotenv(&content) {
+            if !preset.contains(&key) {
+                std::env::set_var(key, value);
+            }
+        }
+    }
+}
+
+fn read_dotenv_var(path: &Path, key: &str) -> Option<String> {
+    parse_dotenv(&read_to_string(path).ok()?)
+        .into_iter()
+        .find_map(|(name, value)| (name == key).then_some(value))
+}
+
+fn parse_dotenv(content: &str) -> Vec<(String, String)> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim();
+            if line.starts_with('#') {
+                return None;
+            }
+            let line = line.strip_prefix("export ").unwrap_or(line);
+            let (name, value) = line.split_once('=')?;
+            Some((
+                name.trim().to_string(),
+                value.trim().trim_matches(['"', '\'']).to_string(),
+            ))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{create_dir_all, remove_dir_all, write};
+
+    use super::*;
+
+    #[test]
+    fn test_resolve_database_url_order() {
+        let root = std::env::temp_dir().join("aromatic_resolve_database_url_order");
+        let crate_dir = root.join("crates").join("api");
+        create_dir_all(&crate_dir).unwrap();
+        write(root.join(".env"), "DATABASE_URL=\"sqlite://dotenv.db\"\n").unwrap();
+
+        let resolved = resolve(None, None, &crate_dir).unwrap().unwrap();
+        assert_eq!(resolved.value, "sqlite://dotenv.db");
+        assert_eq!(resolved.source, ConfigSource::DotEnv(root.join(".env")));
+
+        write(
+            root.join(CONFIG_FILE),
+            "database_url = \"sqlite://toml.db\"\n",
+        )
+        .unwrap();
+        let resolved = resolve(None, None, &crate_dir).unwrap().unwrap();
+        assert_eq!(resolved.value, "sqlite://toml.db");
+        assert_eq!(
+            resolved.source,
+            ConfigSource::ConfigFile(root.join(CONFIG_FILE))
+        );
+
+        let resolved = resolve(None, Some("sqlite://env.db".into()), &crate_dir)
+            .unwrap()
+            .unwrap();
+        assert_eq!(resolved.source, ConfigSource::Env);
+
+        let resolved = resolve(
+            Some("sqlite://flag.db"),
+            Some("sqlite://env.db".into()),
+            &crate_dir,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(resolved.value, "sqlite://flag.db");
+        assert_eq!(resolved.source, ConfigSource::Flag);
+
+        write(
+            root.join(CONFIG_FILE),
+            "databse_url = \"sqlite://toml.db\"\n",
+        )
+        .unwrap();
+        match resolve(None, None, &crate_dir) {
+            Err(AromaticError::InvalidConfig { path, .. }) => {
+                assert_eq!(path, root.join(CONFIG_FILE))
+            },
+            other => panic!("expected an invalid config, got {other:?}"),
+        }
+
+        remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_file_config_retention() {
+        let config: FileConfig = toml::from_str(
+            "[[retention]]\ntable = \"sessions\"\ncolumn = \"created_at\"\nmax_age_days = 30\n",
+        )
+        .unwrap();
+        assert_eq!(config.database_url, None);
+        assert_eq!(
+            config.retention,
+            vec![RetentionPolicy {
+                table: "sessions".to_string(),
+                column: "created_at".to_string(),
+                max_age_days: 30,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_file_config_unknown_keys() {
+        let err = FileConfig::parse(
+            "databse_url = \"sqlite://a.db\"\n[[retention]]\ntable = \"sessions\"\n\
+             column = \"created_at\"\nmax_age = 30\n",
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            "unknown key `databse_url`, did you mean `database_url`?\n\
+             unknown key `retention[0].max_age`, did you mean `retention[0].max_age_days`?"
+        );
+        let err = FileConfig::parse("max_age_days = \"30\"\nfoo = 1\n").unwrap_err();
+        assert!(
+            err.contains("unknown key `foo`, expected one of database_url, retention")
+        );
+        let err = FileConfig::parse("database_url = 3\n").unwrap_err();
+        assert!(err.contains("invalid type"), "{err}");
+    }
+
+    #[test]
+    fn test_parse_dotenv() {
+        let content = "# comment\nexport A=1\nB = \"two\"\n\nC='3=3'\n";
+        assert_eq!(
+            parse_dotenv(content),
+            vec![
+                ("A".to_string(), "1".to_string()),
+                ("B".to_string(), "two".to_string()),
+                ("C".to_string(), "3=3".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,84 @@
+use super::{
+    migrations::{connect, database_url},
+    AromaticError, Backend,
+};
+
+/// SQLite features migrations commonly rely on and the version that introduced them.
+const SQLITE_FEATURES: [(&str, (u32, u32, u32)); 3] = [
+    ("RETURNING", (3, 35, 0)),
+    ("ALTER TABLE DROP COLUMN", (3, 35, 0)),
+    ("STRICT tables", (3, 37, 0)),
+];
+
+/// What `--doctor` found about the configured database.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Diagnosis {
+    pub backend: Backend,
+    pub version: String,
+    /// Features that depend on the version, with the one they need and whether it
+    /// is available.
+    pub features: Vec<Feature>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Feature {
+    pub name: &'static str,
+    pub since: String,
+    pub available: bool,
+}
+
+/// Connects to the database and reports its version. The SQLite one is the library
+/// sqlx compiles in, not the one installed on the machine.
+pub async fn doctor() -> Result<Diagnosis, AromaticError> {
+    let (db_url, backend) = database_url()?;
+    let pool = connect(&db_url).await?;
+    let version: String = sqlx::query_scalar(backend.version_query())
+        .fetch_one(&pool)
+        .await?;
+    Ok(Diagnosis {
+        backend,
+        features: features(backend, &version),
+        version,
+    })
+}
+
+fn features(backend: Backend, version: &str) -> Vec<Feature> {
+    if backend != Backend::Sqlite {
+        return Vec::new();
+    }
+    let current = parse_version(version);
+    SQLITE_FEATURES
+        .into_iter()
+        .map(|(name, since)| Feature {
+            name,
+            since: format!("{}.{}.{}", since.0, since.1, since.2),
+            available: current.is_some_and(|current| current >= since),
+        })
+        .collect()
+}
+
+/// `3.41.2` as `(3, 41, 2)`, missing parts are zero.
+fn parse_version(version: &str) -> Option<(u32, u32, u32)> {
+    let mut parts = version.trim().split('.').map(str::parse);
+    let major = parts.next()?.ok()?;
+    let minor = parts.next().unwrap_or(Ok(0)).ok()?;
+    let patch = parts.next().unwrap_or(Ok(0)).ok()?;
+    Some((major, minor, patch))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sqlite_features() {
+        assert_eq!(parse_version("3.41.2"), Some((3, 41, 2)));
+        assert_eq!(parse_version("3.36"), Some((3, 36, 0)));
+        assert_eq!(parse_version("unknown"), None);
+        let available: Vec<bool> = features(Backend::Sqlite, "3.36.0")
+            .into_iter()
+            .map(|f| f.available)
+            .collect();
+        assert_eq!(available, [true, true, false]);
+    }
+}
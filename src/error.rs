@@ -0,0 +1,134 @@
+use std::{fmt, path::PathBuf};
+
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum AromaticError {
+    #[error("{0}")]
+    Config(String),
+    #[error("invalid {}:\n{message}", path.display())]
+    InvalidConfig { path: PathBuf, message: String },
+    #[error("could not read {}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error(transparent)]
+    Sql(#[from] sqlx::Error),
+    #[error("could not update the migrations history: {0}")]
+    History(#[source] sqlx::Error),
+    #[error("migration {name} failed{}: {source}", at(.location))]
+    Migration {
+        name: String,
+        path: PathBuf,
+        /// The statement that failed, when the database tells which one.
+        location: Option<SqlLocation>,
+        #[source]
+        source: sqlx::Error,
+    },
+    #[error("seed {name} failed: {source}")]
+    Seed {
+        name: String,
+        #[source]
+        source: sqlx::Error,
+    },
+    #[error("integrity check failed after {after}:\n{}", problems.join("\n"))]
+    IntegrityCheck {
+        after: String,
+        problems: Vec<String>,
+    },
+    #[error("invalid SQL in the pending migrations:\n{0}")]
+    InvalidSql(String),
+    #[error("no migration named {0} in the migrations folder")]
+    UnknownMigration(String),
+    #[error("no down migration for {0}")]
+    MissingDown(String),
+    #[error(
+        "migration {0} has no numeric or timestamp prefix, \
+         set ALLOW_UNVERSIONED_MIGRATIONS to run it anyway"
+    )]
+    UnversionedMigration(String),
+    #[error(
+        "migration {name} sorts before {latest} which is already applied, set \
+         OUT_OF_ORDER_MIGRATIONS to warn or skip to apply it or leave it out"
+    )]
+    OutOfOrder { name: String, latest: String },
+    #[error(
+        "migration {name} changed after being applied (recorded checksum {recorded}, \
+         file checksum {found}), set REPAIR_MIGRATION_CHECKSUMS to accept it"
+    )]
+    ChecksumMismatch {
+        name: String,
+        recorded: String,
+        found: String,
+    },
+    #[error(
+        "the application user of DATABASE_URL can change the schema, grant it only \
+         data privileges and run migrations with MIGRATION_DATABASE_URL"
+    )]
+    AppUserCanChangeSchema,
+    #[error(
+        "migrations are locked by another runner, use --force-unlock if it is not \
+         running anymore"
+    )]
+    Locked,
+    #[error(
+        "{0} other session(s) hold transactions open on the database, their locks \
+         would block the migrations"
+    )]
+    OpenTransactions(i64),
+}
+
+/// Statement of a migration file and the line it starts on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SqlLocation {
+    pub line: usize,
+    pub statement: String,
+}
+
+impl fmt::Display for SqlLocation {
+    /// The line and the start of the statement, on a single line.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const SNIPPET_LEN: usize = 60;
+        let statement = self
+            .statement
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        match statement.char_indices().nth(SNIPPET_LEN) {
+            Some((end, _)) => write!(f, "line {} `{}...`", self.line, &statement[..end]),
+            None => write!(f, "line {} `{statement}`", self.line),
+        }
+    }
+}
+
+fn at(location: &Option<SqlLocation>) -> String {
+    location
+        .as_ref()
+        .map(|location| format!(" at {location}"))
+        .unwrap_or_default()
+}
+
+impl AromaticError {
+    /// Serialization failures and deadlocks, the transaction was rolled back and
+    /// running it again can succeed.
+    pub fn is_transient(&self) -> bool {
+        let source = match self {
+            Self::Sql(source)
+            | Self::History(source)
+            | Self::Migration { source, .. }
+            | Self::Seed { source, .. } => source,
+            _ => return false,
+        };
+        let code = source.as_database_error().and_then(|err| err.code());
+        matches!(code.as_deref(), Some("40001" | "40P01"))
+    }
+
+    pub(crate) fn io(path: impl Into<PathBuf>, source: std::io::Error) -> Self {
+        Self::Io {
+            path: path.into(),
+            source,
+        }
+    }
+}
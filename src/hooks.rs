@@ -0,0 +1,59 @@
+use std::{fmt, future::Future, pin::Pin, sync::Arc, time::Duration};
+
+use super::MigrationReport;
+
+pub(crate) type HookFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+type BeforeMigration = Arc<dyn Fn(MigrationEvent) -> HookFuture + Send + Sync>;
+type AfterMigration =
+    Arc<dyn Fn(MigrationEvent, Result<Duration, String>) -> HookFuture + Send + Sync>;
+type Complete = Arc<dyn Fn(Result<MigrationReport, String>) -> HookFuture + Send + Sync>;
+
+/// Migration a hook is called for, the SQL is the up part of the file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationEvent {
+    pub name: String,
+    pub sql: String,
+}
+
+/// Callbacks registered on a [`Migrator`](crate::Migrator), awaited in line so the
+/// run waits for them.
+#[derive(Clone, Default)]
+pub(crate) struct Hooks {
+    pub(crate) before_migration: Option<BeforeMigration>,
+    pub(crate) after_migration: Option<AfterMigration>,
+    pub(crate) complete: Option<Complete>,
+}
+
+impl Hooks {
+    pub(crate) async fn before_migration(&self, event: &MigrationEvent) {
+        if let Some(hook) = &self.before_migration {
+            hook(event.clone()).await;
+        }
+    }
+
+    pub(crate) async fn after_migration(
+        &self,
+        event: MigrationEvent,
+        result: Result<Duration, String>,
+    ) {
+        if let Some(hook) = &self.after_migration {
+            hook(event, result).await;
+        }
+    }
+
+    pub(crate) async fn complete(&self, result: Result<MigrationReport, String>) {
+        if let Some(hook) = &self.complete {
+            hook(result).await;
+        }
+    }
+}
+
+impl fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Hooks")
+            .field("before_migration", &self.before_migration.is_some())
+            .field("after_migration", &self.after_migration.is_some())
+            .field("complete", &self.complete.is_some())
+            .finish()
+    }
+}
@@ -0,0 +1,115 @@
+use std::{
+    fs::{create_dir_all, read_to_string, write, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use super::{config::CONFIG_FILE, AromaticError};
+
+const MIGRATIONS_FOLDER: &str = "migrations/sqlite";
+const FIRST_MIGRATION: &str = "0001_init.sql";
+const DEFAULT_DATABASE_URL: &str = "sqlite://db.sqlite";
+
+const CONFIG_TEMPLATE: &str = r#"# Settings of aromatic, the environment variables take precedence over them.
+
+# Database the application connects to, DATABASE_URL is usually set in .env.
+# database_url = "sqlite://db.sqlite"
+
+# Role allowed to change the schema, when the application's one isn't.
+# migration_database_url = "sqlite://db.sqlite"
+
+# Rows deleted by --cleanup once they are older than max_age_days.
+# [[retention]]
+# table = "sessions"
+# column = "created_at"
+# max_age_days = 30
+"#;
+
+const MIGRATION_TEMPLATE: &str = "-- First migration, run it with --migrate.\n\
+                                  -- Statements after a `-- down` line revert it.\n";
+
+/// Sets up a project in `dir` for `--migrate`: the migrations folder with a first
+/// migration, `aromatic.toml` and a `DATABASE_URL` in `.env`. Files that already
+/// exist are left untouched and `.env` only gets the variable if it is missing.
+/// Returns the paths that were created or changed.
+pub fn init(dir: &Path) -> Result<Vec<PathBuf>, AromaticError> {
+    let mut changed = Vec::new();
+    let folder = dir.join(MIGRATIONS_FOLDER);
+    if !folder.is_dir() {
+        create_dir_all(&folder).map_err(|err| AromaticError::io(&folder, err))?;
+        changed.push(folder.clone());
+    }
+    let is_empty = folder
+        .read_dir()
+        .map_err(|err| AromaticError::io(&folder, err))?
+        .next()
+        .is_none();
+    if is_empty {
+        changed.extend(create(&folder.join(FIRST_MIGRATION), MIGRATION_TEMPLATE)?);
+    }
+    changed.extend(create(&dir.join(CONFIG_FILE), CONFIG_TEMPLATE)?);
+    let env_file = dir.join(".env");
+    let content = read_to_string(&env_file).unwrap_or_default();
+    let has_database_url = content
+        .lines()
+        .any(|line| line.trim_start().starts_with("DATABASE_URL="));
+    if !has_database_url {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&env_file)
+            .map_err(|err| AromaticError::io(&env_file, err))?;
+        let separator = match content.is_empty() || content.ends_with('\n') {
+            true => "",
+            false => "\n",
+        };
+        writeln!(file, "{separator}DATABASE_URL={DEFAULT_DATABASE_URL}")
+            .map_err(|err| AromaticError::io(&env_file, err))?;
+        changed.push(env_file);
+    }
+    Ok(changed)
+}
+
+fn create(path: &Path, content: &str) -> Result<Option<PathBuf>, AromaticError> {
+    if path.exists() {
+        return Ok(None);
+    }
+    write(path, content).map_err(|err| AromaticError::io(path, err))?;
+    Ok(Some(path.to_path_buf()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::remove_dir_all;
+
+    use super::*;
+    use crate::FileConfig;
+
+    #[test]
+    fn test_init() {
+        let root = std::env::temp_dir().join("aromatic_test_init");
+        let _ = remove_dir_all(&root);
+        create_dir_all(&root).unwrap();
+        write(root.join(".env"), "SECRET=1").unwrap();
+
+        let changed = init(&root).unwrap();
+        assert_eq!(
+            changed,
+            [
+                root.join(MIGRATIONS_FOLDER),
+                root.join(MIGRATIONS_FOLDER).join(FIRST_MIGRATION),
+                root.join(CONFIG_FILE),
+                root.join(".env"),
+            ]
+        );
+        assert_eq!(
+            read_to_string(root.join(".env")).unwrap(),
+            "SECRET=1\nDATABASE_URL=sqlite://db.sqlite\n"
+        );
+        let config: FileConfig = toml::from_str(CONFIG_TEMPLATE).unwrap();
+        assert_eq!(config.database_url, None);
+        assert!(init(&root).unwrap().is_empty());
+
+        remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,235 @@
+use std::fmt::Write;
+
+use sqlx::{AnyPool, Row};
+
+use super::{
+    migrations::{connect, database_url, HistoryTable},
+    seeds::SEEDS_TABLE,
+    AromaticError, Backend,
+};
+
+const KEYWORDS: [&str; 20] = [
+    "as", "box", "break", "const", "dyn", "enum", "fn", "impl", "in", "loop", "match",
+    "mod", "move", "ref", "static", "struct", "trait", "type", "use", "where",
+];
+
+/// A table of an existing database, with its columns in declaration order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InspectedTable {
+    pub name: String,
+    pub columns: Vec<InspectedColumn>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InspectedColumn {
+    pub name: String,
+    /// Type as declared, e.g. `VARCHAR(20)` or `integer`.
+    pub data_type: String,
+    pub nullable: bool,
+    pub primary_key: bool,
+}
+
+/// Reads the tables of the database of `DATABASE_URL`, to onboard an existing schema
+/// with [`to_rust_structs`].
+pub async fn inspect_database() -> Result<Vec<InspectedTable>, AromaticError> {
+    let (db_url, backend) = database_url()?;
+    let pool = connect(&db_url).await?;
+    Ok(inspect(backend, &pool).await?)
+}
+
+async fn inspect(
+    backend: Backend,
+    pool: &AnyPool,
+) -> Result<Vec<InspectedTable>, sqlx::Error> {
+    // tables of aromatic itself, left out of the structs
+    let history = HistoryTable::from_env();
+    let lock = history.lock_table();
+    let internal = [history.name(), lock.name(), SEEDS_TABLE];
+    let rows = sqlx::query(columns_query(backend)).fetch_all(pool).await?;
+    let mut tables: Vec<InspectedTable> = Vec::new();
+    for row in rows {
+        let table: String = row.try_get(0)?;
+        if internal.contains(&table.as_str()) {
+            continue;
+        }
+        let column = InspectedColumn {
+            name: row.try_get(1)?,
+            data_type: row.try_get(2)?,
+            nullable: row.try_get::<i64, _>(3)? != 0,
+            primary_key: row.try_get::<i64, _>(4)? != 0,
+        };
+        match tables.last_mut() {
+            Some(last) if last.name == table => last.columns.push(column),
+            _ => tables.push(InspectedTable {
+                name: table,
+                columns: vec![column],
+            }),
+        }
+    }
+    Ok(tables)
+}
+
+/// Table, column, type, nullable and primary key of every column, ordered by table
+/// then position.
+fn columns_query(backend: Backend) -> &'static str {
+    match backend {
+        Backend::Sqlite => {
+            "SELECT t.name, c.name, c.type, \
+             CASE WHEN c.\"notnull\" = 0 AND c.pk = 0 THEN 1 ELSE 0 END, \
+             CASE WHEN c.pk > 0 THEN 1 ELSE 0 END \
+             FROM sqlite_master AS t, pragma_table_info(t.name) AS c \
+             WHERE t.type = 'table' AND t.name NOT LIKE 'sqlite_%' \
+             ORDER BY t.name, c.cid;"
+        },
+        #[cfg(feature = "postgres")]
+        Backend::Postgres => {
+            "SELECT CAST(c.table_name AS TEXT), CAST(c.column_name AS TEXT), \
+             CAST(c.data_type AS TEXT), \
+             CAST(CASE WHEN c.is_nullable = 'YES' THEN 1 ELSE 0 END AS BIGINT), \
+             CAST(CASE WHEN k.column_name IS NULL THEN 0 ELSE 1 END AS BIGINT) \
+             FROM information_schema.columns AS c \
+             LEFT JOIN information_schema.table_constraints AS t \
+             ON t.table_schema = c.table_schema AND t.table_name = c.table_name \
+             AND t.constraint_type = 'PRIMARY KEY' \
+             LEFT JOIN information_schema.key_column_usage AS k \
+             ON k.constraint_name = t.constraint_name \
+             AND k.table_schema = c.table_schema AND k.column_name = c.column_name \
+             WHERE c.table_schema = current_schema() \
+             ORDER BY c.table_name, c.ordinal_position;"
+        },
+        #[cfg(feature = "mysql")]
+        Backend::MySql => {
+            "SELECT CAST(table_name AS CHAR), CAST(column_name AS CHAR), \
+             CAST(column_type AS CHAR), \
+             CAST(CASE WHEN is_nullable = 'YES' THEN 1 ELSE 0 END AS SIGNED), \
+             CAST(CASE WHEN column_key = 'PRI' THEN 1 ELSE 0 END AS SIGNED) \
+             FROM information_schema.columns WHERE table_schema = DATABASE() \
+             ORDER BY table_name, ordinal_position;"
+        },
+    }
+}
+
+/// Structs deriving `sqlx::FromRow` for `tables`, one per table named after it in
+/// the singular, `user_accounts` becomes `UserAccount`.
+pub fn to_rust_structs(tables: &[InspectedTable]) -> String {
+    let mut code = String::from("// Generated by --inspectdb, review the types.\n");
+    for table in tables {
+        let _ = write!(
+            code,
+            "\n#[derive(Debug, sqlx::FromRow)]\npub struct {} {{\n",
+            struct_name(&table.name)
+        );
+        for column in &table.columns {
+            let field = field_name(&column.name);
+            if field.trim_start_matches("r#") != column.name {
+                let _ = writeln!(code, "    #[sqlx(rename = {:?})]", column.name);
+            }
+            let rust_type = rust_type(&column.data_type);
+            let _ = match column.nullable {
+                true => writeln!(code, "    pub {field}: Option<{rust_type}>,"),
+                false => writeln!(code, "    pub {field}: {rust_type},"),
+            };
+        }
+        code.push_str("}\n");
+    }
+    code
+}
+
+/// Type of the field by the declared type, following the SQLite affinity rules,
+/// `String` when nothing matches.
+fn rust_type(data_type: &str) -> &'static str {
+    let data_type = data_type.to_ascii_uppercase();
+    let has = |part: &str| data_type.contains(part);
+    if has("BOOL") || data_type == "TINYINT(1)" {
+        "bool"
+    } else if has("INT") || has("SERIAL") {
+        "i64"
+    } else if has("BLOB") || has("BYTEA") || has("BINARY") {
+        "Vec<u8>"
+    } else if has("REAL") || has("FLOA") || has("DOUB") || has("NUMERIC") || has("DEC") {
+        "f64"
+    } else {
+        "String"
+    }
+}
+
+fn struct_name(table: &str) -> String {
+    let singular = match table {
+        name if name.ends_with("ies") => format!("{}y", &name[..name.len() - 3]),
+        name if name.ends_with('s') && !name.ends_with("ss") => {
+            name[..name.len() - 1].to_string()
+        },
+        name => name.to_string(),
+    };
+    singular
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let mut chars = word.chars();
+            chars
+                .next()
+                .map(|first| first.to_ascii_uppercase().to_string() + chars.as_str())
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+/// `snake_case` identifier for a column, raw when it is a keyword.
+fn field_name(column: &str) -> String {
+    let mut field: String = column
+        .chars()
+        .map(|c| match c.is_ascii_alphanumeric() {
+            true => c.to_ascii_lowercase(),
+            false => '_',
+        })
+        .collect();
+    if field.is_empty() || field.starts_with(|c: char| c.is_ascii_digit()) {
+        field.insert(0, '_');
+    }
+    match KEYWORDS.contains(&field.as_str()) {
+        true => format!("r#{field}"),
+        false => field,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TestDb;
+
+    #[tokio::test]
+    async fn test_inspect() {
+        let db = TestDb::new(
+            "CREATE TABLE categories (id INTEGER PRIMARY KEY, type TEXT NOT NULL);
+             CREATE TABLE user_accounts (
+                 id INTEGER NOT NULL, \"Email Address\" VARCHAR(120), active BOOLEAN,
+                 score REAL, avatar BLOB
+             );
+             CREATE TABLE migrations (id INTEGER);",
+        )
+        .await
+        .unwrap();
+        let tables = inspect(Backend::Sqlite, db.pool()).await.unwrap();
+        assert_eq!(
+            to_rust_structs(&tables),
+            "// Generated by --inspectdb, review the types.
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct Category {
+    pub id: i64,
+    pub r#type: String,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct UserAccount {
+    pub id: i64,
+    #[sqlx(rename = \"Email Address\")]
+    pub email_address: Option<String>,
+    pub active: Option<bool>,
+    pub score: Option<f64>,
+    pub avatar: Option<Vec<u8>>,
+}
+"
+        );
+    }
+}
@@ -1,7 +1,58 @@
+mod activity;
+mod backend;
+mod backup;
 mod cli;
+mod code_migration;
+mod column_types;
+mod config;
+mod doctor;
+mod error;
+mod hooks;
+mod init;
+mod inspect;
+mod lock;
+mod maintenance;
 mod migrations;
+mod migrator;
 mod orm;
+mod pragmas;
+pub mod prelude;
+mod privileges;
+mod retention;
+mod seeds;
+mod statements;
+#[cfg(any(test, feature = "testing"))]
+mod testing;
+mod triggers;
 
+pub use activity::OpenTransactionsPolicy;
+pub use backend::Backend;
+pub use backup::restore_backup;
 pub use cli::run_cli;
-pub use migrations::migrate;
-pub use orm::Orm;
+pub use code_migration::{BoxFuture, Migration};
+pub use column_types::{audit_column_types, TextInTypedColumn};
+pub use config::{
+    find_file_config, resolve_database_url, validate_file_config, ConfigSource,
+    FileConfig, Resolved, RetentionPolicy,
+};
+pub use doctor::{doctor, Diagnosis, Feature};
+pub use error::{AromaticError, SqlLocation};
+pub use hooks::MigrationEvent;
+pub use init::init;
+pub use inspect::{inspect_database, to_rust_structs, InspectedColumn, InspectedTable};
+pub use maintenance::{maintain, Maintenance, MaintenanceTask};
+pub use migrations::{
+    baseline, fake, force_unlock, migrate, migrate_embedded, migrate_to,
+    migrate_with_pool, migrate_with_shutdown, migration_history, migration_status, redo,
+    rollback, validate, EmbeddedMigration, HistoryRecord, HistoryState, IntegrityChecks,
+    MigrationReport, MigrationState, MigrationStatus, OutOfOrderPolicy, TestMarker,
+};
+pub use migrator::{Migrator, MigratorBuilder};
+pub use orm::{Orm, Value};
+pub use pragmas::SqlitePragmas;
+pub use privileges::check_app_privileges;
+pub use retention::{backfill, cleanup, Throttle, DEFAULT_BATCH_SIZE};
+pub use seeds::seed;
+#[cfg(any(test, feature = "testing"))]
+pub use testing::TestDb;
+pub use triggers::Trigger;
@@ -0,0 +1,188 @@
+use std::time::{Duration, Instant};
+
+use sqlx::AnyPool;
+use tracing::{error, warn};
+
+use super::{migrations::HistoryTable, AromaticError, Backend, Orm};
+
+const LOCK_ID: &str = "1";
+const DEFAULT_LOCK_TIMEOUT: u64 = 60;
+const RETRY_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Row held in the lock table of the history, `migrations_lock` for `migrations`,
+/// while a runner applies or reverts migrations, so instances booting at the same
+/// time run them one after the other. Services keeping separate histories don't wait
+/// for each other. It works the same on every backend, unlike advisory locks, and is
+/// released with [`MigrationLock::release`], or [`clear_lock`] if the holder died.
+pub(crate) struct MigrationLock<'a> {
+    pool: &'a AnyPool,
+    table: String,
+}
+
+impl<'a> MigrationLock<'a> {
+    /// Waits until the lock of `history` is free, at most `timeout`.
+    pub(crate) async fn acquire(
+        backend: Backend,
+        pool: &'a AnyPool,
+        history: &HistoryTable,
+        timeout: Duration,
+    ) -> Result<MigrationLock<'a>, AromaticError> {
+        let table = history.lock_table().quoted(backend);
+        sqlx::query(&lock_table_ddl(&table)).execute(pool).await?;
+        let started = Instant::now();
+        let mut waiting = false;
+        loop {
+            match try_lock(backend, pool, &table).await {
+                Ok(true) => return Ok(Self { pool, table }),
+                // on SQLite the holder may keep the database busy while migrating
+                Ok(false) => (),
+                Err(err) if is_busy(&err) => (),
+                Err(err) => return Err(err.into()),
+            }
+            if started.elapsed() >= timeout {
+                return Err(AromaticError::Locked);
+            }
+            if !waiting {
+                warn!(
+                    function = "MigrationLock::acquire",
+                    message = "Migrations are locked by another runner, waiting",
+                );
+                waiting = true;
+            }
+            tokio::time::sleep(RETRY_INTERVAL).await;
+        }
+    }
+
+    pub(crate) async fn release(self) {
+        if let Err(err) = delete_lock(self.pool, &self.table).await {
+            error!(
+                function = "MigrationLock::release",
+                error_message = format!("{err}"),
+                message = "Could not release the migrations lock",
+            );
+        }
+    }
+}
+
+/// Takes the lock if nobody holds it. The row is looked up first and the insert
+/// ignores conflicts instead of failing, sqlx steps a failed SQLite statement again
+/// and that retry could take the lock on behalf of nobody once it is released.
+async fn try_lock(
+    backend: Backend,
+    pool: &AnyPool,
+    table: &str,
+) -> Result<bool, sqlx::Error> {
+    let query = Orm::select("id")
+        .from(table)
+        .where_()
+        .equal("id", LOCK_ID)
+        .ready();
+    if sqlx::query(&query).fetch_optional(pool).await?.is_some() {
+        return Ok(false);
+    }
+    let query = backend.insert_or_ignore(table, "id", LOCK_ID);
+    let result = sqlx::query(&query).execute(pool).await?;
+    Ok(result.rows_affected() == 1)
+}
+
+/// Errors of a database busy with another transaction: SQLite `SQLITE_BUSY` and
+/// `SQLITE_LOCKED`, whatever their extended code, and Postgres lock and
+/// serialization failures or deadlocks. Anything else won't go away by waiting.
+fn is_busy(err: &sqlx::Error) -> bool {
+    let Some(code) = err.as_database_error().and_then(|err| err.code()) else {
+        return false;
+    };
+    match code.parse::<i32>() {
+        Ok(code) => matches!(code & 0xff, 5 | 6),
+        Err(_) => matches!(code.as_ref(), "55P03" | "40001" | "40P01"),
+    }
+}
+
+/// Removes the lock of `history` left behind by a runner that did not finish.
+pub(crate) async fn clear_lock(
+    backend: Backend,
+    pool: &AnyPool,
+    history: &HistoryTable,
+) -> Result<bool, AromaticError> {
+    let table = history.lock_table().quoted(backend);
+    sqlx::query(&lock_table_ddl(&table)).execute(pool).await?;
+    Ok(delete_lock(pool, &table).await? > 0)
+}
+
+async fn delete_lock(pool: &AnyPool, table: &str) -> Result<u64, sqlx::Error> {
+    let query = Orm::delete(&format!("FROM {table}"))
+        .where_()
+        .equal("id", LOCK_ID)
+        .ready();
+    Ok(sqlx::query(&query).execute(pool).await?.rows_affected())
+}
+
+fn lock_table_ddl(table: &str) -> String {
+    format!(
+        r#"
+        CREATE TABLE IF NOT EXISTS {table} (
+            id INTEGER NOT NULL PRIMARY KEY,
+            locked_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+        );
+        "#
+    )
+}
+
+/// How long to wait for another runner, `MIGRATION_LOCK_TIMEOUT` seconds.
+pub(crate) fn lock_timeout() -> Duration {
+    let seconds = std::env::var("MIGRATION_LOCK_TIMEOUT")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(DEFAULT_LOCK_TIMEOUT);
+    Duration::from_secs(seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TestDb;
+
+    #[tokio::test]
+    async fn test_lock_per_history() {
+        let db = TestDb::new("").await.unwrap();
+        let (timeout, backend) = (Duration::ZERO, Backend::Sqlite);
+        let migrations = HistoryTable::parse("migrations");
+        let billing = HistoryTable::parse("billing_migrations");
+        let lock = MigrationLock::acquire(backend, db.pool(), &migrations, timeout)
+            .await
+            .unwrap();
+        assert!(matches!(
+            MigrationLock::acquire(backend, db.pool(), &migrations, timeout).await,
+            Err(AromaticError::Locked)
+        ));
+        let other = MigrationLock::acquire(backend, db.pool(), &billing, timeout)
+            .await
+            .unwrap();
+        other.release().await;
+        assert!(!clear_lock(backend, db.pool(), &billing).await.unwrap());
+        lock.release().await;
+        db.assert_rows("SELECT COUNT(*) FROM migrations_lock;", &[&["0"]])
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_lock_fails_on_other_errors() {
+        let db = TestDb::new(
+            "CREATE TABLE migrations_lock (id INTEGER PRIMARY KEY, locked_at TIMESTAMP);
+             CREATE TRIGGER deny BEFORE INSERT ON migrations_lock
+             BEGIN SELECT RAISE(ABORT, 'denied'); END;",
+        )
+        .await
+        .unwrap();
+        let started = Instant::now();
+        let result = MigrationLock::acquire(
+            Backend::Sqlite,
+            db.pool(),
+            &HistoryTable::parse("migrations"),
+            Duration::from_secs(30),
+        )
+        .await;
+        assert!(matches!(result, Err(AromaticError::Sql(_))));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,140 @@
+use std::time::{Duration, Instant};
+
+use sqlx::{AnyConnection, AnyPool, Executor, Row};
+use tracing::error;
+
+use super::{
+    migrations::{connect, database_url},
+    AromaticError, Backend,
+};
+
+/// Routine upkeep of a SQLite database, run by `--maintain`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MaintenanceTask {
+    /// Moves the write-ahead log into the database and truncates it.
+    WalCheckpoint,
+    /// Rebuilds the database file to reclaim the space of deleted rows.
+    Vacuum,
+    /// Refreshes the statistics the query planner relies on.
+    Analyze,
+    /// Checks the whole database for corruption.
+    IntegrityCheck,
+}
+
+impl MaintenanceTask {
+    pub const ALL: [Self; 4] = [
+        Self::WalCheckpoint,
+        Self::Vacuum,
+        Self::Analyze,
+        Self::IntegrityCheck,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::WalCheckpoint => "checkpoint",
+            Self::Vacuum => "vacuum",
+            Self::Analyze => "analyze",
+            Self::IntegrityCheck => "integrity-check",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|task| task.name() == name)
+    }
+
+    async fn run(&self, pool: &AnyPool) -> Result<Vec<String>, sqlx::Error> {
+        match self {
+            Self::WalCheckpoint => {
+                let row = pool.fetch_one("PRAGMA wal_checkpoint(TRUNCATE);").await?;
+                Ok(vec![format!(
+                    "busy={} log={} checkpointed={}",
+                    row.try_get::<i64, _>(0)?,
+                    row.try_get::<i64, _>(1)?,
+                    row.try_get::<i64, _>(2)?
+                )])
+            },
+            Self::Vacuum => pool.execute("VACUUM;").await.map(|_| Vec::new()),
+            Self::Analyze => pool.execute("ANALYZE;").await.map(|_| Vec::new()),
+            Self::IntegrityCheck => {
+                sqlx::query_scalar("PRAGMA integrity_check;")
+                    .fetch_all(pool)
+                    .await
+            },
+        }
+    }
+}
+
+/// What a task did and how long it took. The integrity check outputs `ok` or the
+/// problems it found, the checkpoint the frames of the log it moved.
+#[derive(Debug)]
+pub struct Maintenance {
+    pub task: MaintenanceTask,
+    pub duration: Duration,
+    pub output: Vec<String>,
+}
+
+/// Runs `tasks` in order on the SQLite database of `DATABASE_URL`.
+pub async fn maintain(
+    tasks: &[MaintenanceTask],
+) -> Result<Vec<Maintenance>, AromaticError> {
+    let (db_url, backend) = database_url()?;
+    if backend != Backend::Sqlite {
+        return Err(AromaticError::Config(
+            "maintenance tasks are only available for SQLite".to_string(),
+        ));
+    }
+    let pool = connect(&db_url).await?;
+    let mut done = Vec::with_capacity(tasks.len());
+    for task in tasks {
+        let started = Instant::now();
+        let output = match task.run(&pool).await {
+            Ok(output) => output,
+            Err(err) => {
+                error!(
+                    function = "maintain",
+                    error_message = format!("{err}"),
+                    message = format!("Could not run {}", task.name()),
+                );
+                return Err(err.into());
+            },
+        };
+        done.push(Maintenance {
+            task: *task,
+            duration: started.elapsed(),
+            output,
+        });
+    }
+    Ok(done)
+}
+
+/// Corruption found by `PRAGMA integrity_check` and rows referencing missing ones found
+/// by `PRAGMA foreign_key_check`, empty when the database is sound.
+pub(crate) async fn integrity_problems(
+    connection: &mut AnyConnection,
+) -> Result<Vec<String>, sqlx::Error> {
+    let mut problems: Vec<String> = sqlx::query_scalar("PRAGMA integrity_check;")
+        .fetch_all(&mut *connection)
+        .await?;
+    problems.retain(|problem| problem != "ok");
+    let violations: Vec<String> = sqlx::query_scalar(
+        "SELECT \"table\" || ' row ' || COALESCE(rowid, '?') || \
+         ' references a missing row of ' || parent FROM pragma_foreign_key_check;",
+    )
+    .fetch_all(&mut *connection)
+    .await?;
+    problems.extend(violations);
+    Ok(problems)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_task_names() {
+        for task in MaintenanceTask::ALL {
+            assert_eq!(MaintenanceTask::from_name(task.name()), Some(task));
+        }
+        assert_eq!(MaintenanceTask::from_name("all"), None);
+    }
+}
@@ -1,31 +1,271 @@
 use std::{
-    collections::HashMap,
-    fs::{read_dir, DirEntry},
-    path::PathBuf,
+    collections::{HashMap, HashSet},
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, OnceLock,
+    },
+    time::{Duration, Instant},
 };
 
-use menva::{get_bool_env, get_env};
+use menva::get_bool_env;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
 use sqlx::{
-    migrate::MigrateDatabase, sqlite::SqliteConnection, FromRow, Sqlite, SqlitePool,
-    Transaction,
+    any::{install_default_drivers, AnyRow},
+    migrate::MigrateDatabase,
+    Any, AnyConnection, AnyPool, Executor, FromRow, Row, Transaction, TypeInfo, ValueRef,
 };
-use tracing::error;
+use tracing::{error, warn};
 
-use super::Orm;
+use super::{
+    activity::OpenTransactionsPolicy,
+    backup::backup_sqlite,
+    code_migration::Migration as CodeMigration,
+    find_file_config,
+    hooks::Hooks,
+    lock::{clear_lock, lock_timeout, MigrationLock},
+    maintenance::integrity_problems,
+    migrator::MigrationsSource,
+    pragmas::SqlitePragmas,
+    resolve_database_url,
+    statements::split_statements,
+    AromaticError, Backend, MigrationEvent, Migrator, Orm, SqlLocation, Value,
+};
 
-#[derive(Debug)]
-enum MigrationError {
-    Failed,
+/// Columns read back from the history table, normalized because the `Any` driver
+/// can't map SQLite booleans, Postgres timestamps nor MySQL text columns.
+fn migration_columns(backend: Backend) -> String {
+    format!(
+        "id, {} AS name, {} AS path, CASE WHEN ran THEN 1 ELSE 0 END AS ran, \
+         {} AS timestamp, {} AS down, {} AS checksum",
+        backend.cast_to_text("name"),
+        backend.cast_to_text("path"),
+        backend.cast_to_text("timestamp"),
+        backend.cast_to_text("down"),
+        backend.cast_to_text("checksum"),
+    )
 }
 
 #[allow(dead_code)]
-#[derive(FromRow, Debug)]
+#[derive(Debug)]
 struct Migration {
-    id: u32,
+    id: i64,
     name: String,
     path: String,
     ran: bool,
     timestamp: String,
+    down: Option<String>,
+    checksum: Option<String>,
+}
+
+impl FromRow<'_, AnyRow> for Migration {
+    fn from_row(row: &AnyRow) -> Result<Self, sqlx::Error> {
+        Ok(Self {
+            id: row.try_get("id")?,
+            name: row.try_get("name")?,
+            path: row.try_get("path")?,
+            ran: row.try_get::<i64, _>("ran")? != 0,
+            timestamp: row.try_get("timestamp")?,
+            down: optional_text(row, "down")?,
+            checksum: optional_text(row, "checksum")?,
+        })
+    }
+}
+
+/// The `Any` driver never reports values as null, so `Option<String>` can't be
+/// decoded directly from a nullable column.
+fn optional_text(row: &AnyRow, column: &str) -> Result<Option<String>, sqlx::Error> {
+    if row.try_get_raw(column)?.type_info().name() == "NULL" {
+        return Ok(None);
+    }
+    row.try_get(column).map(Some)
+}
+
+/// What a migration run did, serialized as JSON by `--report json`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct MigrationReport {
+    /// Migrations executed in this run, in order.
+    pub applied: Vec<String>,
+    /// Checksum of each migration in `applied`, in the same order.
+    pub checksums: Vec<String>,
+    /// How long each migration in `applied` took, in the same order.
+    pub durations_ms: Vec<u64>,
+    /// Pending migrations left out: test migrations while `RUN_TEST_MIGRATIONS` is
+    /// off, and out of order ones with [`OutOfOrderPolicy::Skip`].
+    pub skipped: Vec<String>,
+    /// Set when a shutdown was requested, first migration that was not run.
+    /// Everything before it has been committed along with its history.
+    pub interrupted_before: Option<String>,
+    /// With `DRY_RUN_MIGRATIONS` set, the SQL of each migration in `applied`, in the
+    /// same order. Nothing was committed.
+    pub sql: Vec<String>,
+    /// Migrations [`migrate_to`] reverted because they come after its target, newest
+    /// first.
+    pub reverted: Vec<String>,
+    /// Copy of the SQLite database made before applying anything, with
+    /// `BACKUP_BEFORE_MIGRATIONS` set. See [`restore_backup`](crate::restore_backup).
+    pub backup: Option<PathBuf>,
+}
+
+impl MigrationReport {
+    fn applied(
+        &mut self,
+        name: String,
+        sql: MigrationSql,
+        duration: Duration,
+        dry_run: bool,
+    ) {
+        self.applied.push(name);
+        self.checksums.push(sql.checksum);
+        self.durations_ms
+            .push(u64::try_from(duration.as_millis()).unwrap_or(u64::MAX));
+        if dry_run {
+            self.sql.push(sql.up);
+        }
+    }
+}
+
+/// Where a migration stands compared to the history, see [`migration_status`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MigrationState {
+    Applied,
+    Pending,
+    /// Applied according to the history but its file is gone.
+    MissingOnDisk,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct MigrationStatus {
+    pub name: String,
+    pub state: MigrationState,
+    pub applied_at: Option<String>,
+}
+
+/// What happened to a migration according to the history, see
+/// [`migration_history`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HistoryState {
+    Applied,
+    Reverted,
+    /// Its last run failed and was rolled back.
+    Failed,
+}
+
+/// A row of the history table.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct HistoryRecord {
+    pub name: String,
+    pub state: HistoryState,
+    /// When it was first recorded.
+    pub applied_at: String,
+    pub duration_ms: Option<i64>,
+    pub checksum: Option<String>,
+    pub applied_by: Option<String>,
+    pub app_version: Option<String>,
+    /// Why the last run failed.
+    pub error: Option<String>,
+}
+
+impl FromRow<'_, AnyRow> for HistoryRecord {
+    fn from_row(row: &AnyRow) -> Result<Self, sqlx::Error> {
+        let error = optional_text(row, "error")?;
+        let state = match (row.try_get::<i64, _>("ran")? != 0, &error) {
+            (true, _) => HistoryState::Applied,
+            (false, Some(_)) => HistoryState::Failed,
+            (false, None) => HistoryState::Reverted,
+        };
+        Ok(Self {
+            name: row.try_get("name")?,
+            state,
+            applied_at: row.try_get("timestamp")?,
+            duration_ms: optional_text(row, "duration_ms")?
+                .and_then(|duration| duration.parse().ok()),
+            checksum: optional_text(row, "checksum")?,
+            applied_by: optional_text(row, "applied_by")?,
+            app_version: optional_text(row, "app_version")?,
+            error,
+        })
+    }
+}
+
+/// A migration file compiled into the binary, see [`embed_migrations!`].
+///
+/// [`embed_migrations!`]: crate::embed_migrations
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EmbeddedMigration {
+    pub name: &'static str,
+    pub sql: &'static str,
+}
+
+/// Embeds the listed files of `folder`, relative to the crate root, to be run with
+/// [`migrate_embedded`](crate::migrate_embedded). Paired `.down.sql` files can be
+/// listed too.
+///
+/// ```ignore
+/// let migrations = aromatic::embed_migrations!("migrations", [
+///     "0001_users.sql",
+///     "0002_posts.up.sql",
+///     "0002_posts.down.sql",
+/// ]);
+/// aromatic::migrate_embedded(migrations).await?;
+/// ```
+#[macro_export]
+macro_rules! embed_migrations {
+    ($folder:literal, [$($file:literal),* $(,)?]) => {
+        &[$($crate::EmbeddedMigration {
+            name: $file,
+            sql: include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/", $folder, "/", $file)),
+        }),*]
+    };
+}
+
+/// Where the migration files come from.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Source<'a> {
+    Folder(&'a str),
+    Embedded(&'static [EmbeddedMigration]),
+}
+
+const DOWN_SUFFIX: &str = ".down.sql";
+const DEFAULT_EXTENSION: &str = "sql";
+
+/// How test migrations, left out unless `RUN_TEST_MIGRATIONS` is set, are told
+/// apart from the others.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TestMarker {
+    /// The file name contains it, `test` by default.
+    Contains(String),
+    /// The file name ends with it, e.g. `.test.sql`.
+    Suffix(String),
+    /// The file is in this subfolder of the migrations folder, e.g. `test`.
+    Folder(String),
+}
+
+impl Default for TestMarker {
+    fn default() -> Self {
+        Self::Contains("test".to_string())
+    }
+}
+
+impl TestMarker {
+    fn matches(&self, migration_file: &MigrationFile) -> bool {
+        match self {
+            Self::Contains(marker) => migration_file.name.contains(marker.as_str()),
+            Self::Suffix(suffix) => migration_file.name.ends_with(suffix.as_str()),
+            Self::Folder(folder) => {
+                migration_file.embedded.is_none()
+                    && migration_file
+                        .path
+                        .parent()
+                        .and_then(Path::file_name)
+                        .is_some_and(|parent| parent == folder.as_str())
+            },
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -33,85 +273,1127 @@ struct MigrationFile {
     name: String,
     ran: bool,
     path: PathBuf,
+    down_path: Option<PathBuf>,
+    version: Option<(u64, u64)>,
+    /// Contents of the file and of its down file when they are embedded.
+    embedded: Option<(&'static str, Option<&'static str>)>,
+    code: Option<Arc<dyn CodeMigration>>,
+    /// Namespace of the source it comes from, which prefixes its name.
+    namespace: Option<String>,
 }
 
 impl MigrationFile {
-    fn new(entry: DirEntry) -> Self {
+    /// File at `path` of a folder holding the files `names`, paired with its
+    /// `.down.sql` file when there is one.
+    fn new(path: PathBuf, names: &HashSet<String>) -> Self {
+        let mut migration_file = Self::from_path(path);
+        migration_file.down_path = migration_file
+            .name
+            .strip_suffix(".up.sql")
+            .map(|stem| format!("{stem}{DOWN_SUFFIX}"))
+            .filter(|down| names.contains(down))
+            .map(|down| migration_file.path.with_file_name(down));
+        migration_file
+    }
+
+    fn from_path(path: PathBuf) -> Self {
+        let name = path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        Self {
+            version: Self::parse_version(&name),
+            name,
+            ran: false,
+            path,
+            down_path: None,
+            embedded: None,
+            code: None,
+            namespace: None,
+        }
+    }
+
+    fn embedded(migration: &EmbeddedMigration, all: &[EmbeddedMigration]) -> Self {
+        let down = migration.name.strip_suffix(".up.sql").and_then(|stem| {
+            all.iter()
+                .find(|m| m.name.strip_suffix(DOWN_SUFFIX) == Some(stem))
+                .map(|m| m.sql)
+        });
         Self {
-            name: entry.file_name().to_string_lossy().to_string(),
+            version: Self::parse_version(migration.name),
+            name: migration.name.to_string(),
             ran: false,
-            path: entry.path(),
+            path: PathBuf::from(migration.name),
+            down_path: None,
+            embedded: Some((migration.sql, down)),
+            code: None,
+            namespace: None,
+        }
+    }
+
+    fn code(migration: &Arc<dyn CodeMigration>) -> Self {
+        Self {
+            code: Some(migration.clone()),
+            ..Self::from_path(PathBuf::from(migration.name()))
         }
     }
+
+    /// Names it `namespace/name`, its version stays the one of the file name.
+    fn namespaced(self, namespace: &str) -> Self {
+        Self {
+            name: format!("{namespace}/{}", self.name),
+            namespace: Some(namespace.to_string()),
+            ..self
+        }
+    }
+
+    /// Leading sequence number or timestamp of the file name, `0001_users.sql` or
+    /// `20230801120000_users.sql`, and the sequence after a dash that orders files
+    /// sharing a timestamp, `20230801120000-2_posts.sql`. It is 0 when absent.
+    fn parse_version(name: &str) -> Option<(u64, u64)> {
+        let digits = name.split(|c: char| !c.is_ascii_digit()).next()?;
+        let version = digits.parse().ok()?;
+        let sequence = name[digits.len()..]
+            .strip_prefix('-')
+            .and_then(|rest| rest.split(|c: char| !c.is_ascii_digit()).next())
+            .and_then(|digits| digits.parse().ok())
+            .unwrap_or(0);
+        Some((version, sequence))
+    }
 }
 
-pub async fn migrate(folder_path: &str) {
-    let db_url = get_env("DATABASE_URL");
-    create_database(&db_url).await;
+/// What a run does with a pending migration sorting before the latest applied one,
+/// typically merged from a branch created before it. From `OUT_OF_ORDER_MIGRATIONS`:
+/// `warn` or `skip`, anything else fails.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OutOfOrderPolicy {
+    #[default]
+    Error,
+    /// Logs it and applies it.
+    Warn,
+    /// Leaves it out of the run.
+    Skip,
+}
 
-    let mut transaction = match transaction().await {
-        Ok(t) => t,
-        Err(err) => {
-            error!(
-                function = "migrate",
-                error_message = format!("{err}"),
-                message = "Could not start transaction",
-            );
-            return;
-        },
-    };
-    let _ = create_migrations_table(&mut transaction)
+impl OutOfOrderPolicy {
+    fn from_env() -> Self {
+        match std::env::var("OUT_OF_ORDER_MIGRATIONS").as_deref() {
+            Ok("warn") => Self::Warn,
+            Ok("skip") => Self::Skip,
+            _ => Self::Error,
+        }
+    }
+}
+
+/// Checks of a SQLite database for corruption and rows referencing missing ones after
+/// migrating, as a migration rebuilding a table can break references unnoticed.
+/// From `MIGRATION_INTEGRITY_CHECKS`: `fail` or `rollback`, anything else skips them.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum IntegrityChecks {
+    #[default]
+    Off,
+    /// Checks once the run is committed and fails if there are problems.
+    Fail,
+    /// Checks each migration before committing it, rolling it back on problems.
+    Rollback,
+}
+
+impl IntegrityChecks {
+    fn from_env() -> Self {
+        match std::env::var("MIGRATION_INTEGRITY_CHECKS").as_deref() {
+            Ok("fail") => Self::Fail,
+            Ok("rollback") => Self::Rollback,
+            _ => Self::Off,
+        }
+    }
+}
+
+/// The statements of a migration, the down part is only present when the file has a
+/// `-- down` section or a paired `.down.sql` file. The checksum is the hex SHA-256 of
+/// the migration file. A `-- no-transaction` line makes it run outside of any
+/// transaction, for statements like `CREATE INDEX CONCURRENTLY` that refuse to.
+#[derive(Debug, PartialEq, Eq)]
+struct MigrationSql {
+    up: String,
+    down: Option<String>,
+    checksum: String,
+    no_transaction: bool,
+    /// Lines of the file, starting at 1, left out of `up` because they are markers.
+    markers: Vec<usize>,
+}
+
+impl MigrationSql {
+    const DOWN_MARKER: &'static str = "-- down";
+    const NO_TRANSACTION_MARKER: &'static str = "-- no-transaction";
+
+    fn parse(sql: &str) -> Self {
+        let mut up = Vec::new();
+        let mut down = Vec::new();
+        let mut in_down = false;
+        let mut no_transaction = false;
+        let mut markers = Vec::new();
+        for (number, line) in sql.lines().enumerate() {
+            if line.trim().eq_ignore_ascii_case(Self::DOWN_MARKER) {
+                in_down = true;
+                continue;
+            }
+            if line
+                .trim()
+                .eq_ignore_ascii_case(Self::NO_TRANSACTION_MARKER)
+            {
+                no_transaction = true;
+                if !in_down {
+                    markers.push(number + 1);
+                }
+                continue;
+            }
+            match in_down {
+                true => down.push(line),
+                false => up.push(line),
+            }
+        }
+        Self {
+            up: up.join("\n"),
+            down: in_down.then(|| down.join("\n")),
+            checksum: Self::checksum(sql),
+            no_transaction,
+            markers,
+        }
+    }
+
+    /// The statement of `up` at `statement` and the line of the file it starts on,
+    /// or the one of the error when its `offset` in the statement is known.
+    fn locate(&self, statement: Range<usize>, offset: Option<usize>) -> SqlLocation {
+        let at = (statement.start + offset.unwrap_or(0)).min(statement.end);
+        let mut line = self.up[..at].matches('\n').count() + 1;
+        for marker in &self.markers {
+            if *marker <= line {
+                line += 1;
+            }
+        }
+        SqlLocation {
+            line,
+            statement: self.up[statement].trim().to_string(),
+        }
+    }
+
+    fn checksum(sql: &str) -> String {
+        Sha256::digest(sql.as_bytes())
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect()
+    }
+}
+
+const DEFAULT_MIGRATION_RETRIES: u32 = 3;
+const RETRY_BACKOFF: Duration = Duration::from_millis(200);
+
+/// How a run behaves, set with [`MigratorBuilder`](crate::MigratorBuilder). The
+/// free functions read every option from the environment.
+#[derive(Debug, Clone)]
+pub(crate) struct Settings {
+    pub(crate) dry_run: bool,
+    pub(crate) backup: bool,
+    pub(crate) single_transaction: bool,
+    pub(crate) run_test_migrations: bool,
+    pub(crate) test_marker: TestMarker,
+    pub(crate) allow_unversioned: bool,
+    pub(crate) extensions: Vec<String>,
+    pub(crate) recursive: bool,
+    pub(crate) out_of_order: OutOfOrderPolicy,
+    pub(crate) integrity_checks: IntegrityChecks,
+    pub(crate) repair_checksums: bool,
+    pub(crate) retries: u32,
+    pub(crate) lock_timeout: Duration,
+    pub(crate) connect_timeout: Duration,
+    pub(crate) open_transactions: OpenTransactionsPolicy,
+    pub(crate) sqlite_pragmas: SqlitePragmas,
+    pub(crate) table: HistoryTable,
+    pub(crate) app_version: Option<String>,
+    pub(crate) hooks: Hooks,
+    pub(crate) code_migrations: Vec<Arc<dyn CodeMigration>>,
+    pub(crate) namespaces: Vec<(String, MigrationsSource)>,
+}
+
+impl Settings {
+    pub(crate) fn from_env() -> Self {
+        Self {
+            dry_run: get_bool_env("DRY_RUN_MIGRATIONS"),
+            backup: get_bool_env("BACKUP_BEFORE_MIGRATIONS"),
+            single_transaction: get_bool_env("SINGLE_TRANSACTION_MIGRATIONS"),
+            run_test_migrations: get_bool_env("RUN_TEST_MIGRATIONS"),
+            test_marker: TestMarker::default(),
+            allow_unversioned: get_bool_env("ALLOW_UNVERSIONED_MIGRATIONS"),
+            extensions: migration_extensions(),
+            recursive: get_bool_env("RECURSIVE_MIGRATIONS"),
+            out_of_order: OutOfOrderPolicy::from_env(),
+            integrity_checks: IntegrityChecks::from_env(),
+            repair_checksums: get_bool_env("REPAIR_MIGRATION_CHECKSUMS"),
+            retries: migration_retries(),
+            lock_timeout: lock_timeout(),
+            connect_timeout: connect_timeout(),
+            open_transactions: OpenTransactionsPolicy::from_env(),
+            sqlite_pragmas: SqlitePragmas::from_env(),
+            table: HistoryTable::from_env(),
+            app_version: std::env::var("MIGRATION_APP_VERSION")
+                .ok()
+                .filter(|value| !value.is_empty()),
+            hooks: Hooks::default(),
+            code_migrations: Vec::new(),
+            namespaces: Vec::new(),
+        }
+    }
+}
+
+pub async fn migrate(folder_path: &str) -> Result<MigrationReport, AromaticError> {
+    Migrator::builder()
+        .folder(folder_path)
+        .build()
+        .migrate()
         .await
-        .map_err(|err| {
-            error!(
-                function = "create_migrations_table",
-                error_message = format!("{err}"),
-                message = "Could not create the migrations table",
-            );
-        });
-    let migrations_history = match get_migrations_history(&mut transaction).await {
-        Ok(m) => m,
-        Err(err) => {
-            error!(
-                function = "get_migrations_history",
-                error_message = format!("{err}"),
-                message = "Could not get migrations history",
-            );
-            return;
-        },
+}
+
+/// Same as [`migrate`] but checks `shutdown` before each migration. Once it is set
+/// the migration in progress is allowed to finish, the transaction is committed and
+/// the remaining files are left pending.
+pub async fn migrate_with_shutdown(
+    folder_path: &str,
+    shutdown: &AtomicBool,
+) -> Result<MigrationReport, AromaticError> {
+    Migrator::builder()
+        .folder(folder_path)
+        .build()
+        .migrate_with_shutdown(shutdown)
+        .await
+}
+
+/// Same as [`migrate`] with migrations compiled into the binary by
+/// [`embed_migrations!`](crate::embed_migrations), so they don't need to be shipped
+/// along with it.
+pub async fn migrate_embedded(
+    migrations: &'static [EmbeddedMigration],
+) -> Result<MigrationReport, AromaticError> {
+    Migrator::builder()
+        .embedded(migrations)
+        .build()
+        .migrate()
+        .await
+}
+
+/// Same as [`migrate`] on a pool the application already manages, for instance with
+/// its own connect options. `DATABASE_URL` is not read and the database is not
+/// created.
+pub async fn migrate_with_pool(
+    pool: &AnyPool,
+    folder_path: &str,
+) -> Result<MigrationReport, AromaticError> {
+    Migrator::builder()
+        .pool(pool.clone())
+        .folder(folder_path)
+        .build()
+        .migrate()
+        .await
+}
+
+/// Brings the database to the migration named `target`: the pending ones up to and
+/// including it are applied and the applied ones that come after it are reverted,
+/// newest first, using their down SQL.
+pub async fn migrate_to(
+    folder_path: &str,
+    target: &str,
+) -> Result<MigrationReport, AromaticError> {
+    Migrator::builder()
+        .folder(folder_path)
+        .build()
+        .migrate_to(target)
+        .await
+}
+
+pub(crate) async fn run_migrate(
+    backend: Backend,
+    pool: &AnyPool,
+    source: Source<'_>,
+    settings: &Settings,
+    shutdown: &AtomicBool,
+) -> Result<MigrationReport, AromaticError> {
+    settings
+        .open_transactions
+        .guard(backend, pool, settings.lock_timeout)
+        .await?;
+    let lock =
+        MigrationLock::acquire(backend, pool, &settings.table, settings.lock_timeout)
+            .await?;
+    let report = async {
+        let backup = backup_before_run(backend, pool, source, settings).await?;
+        let report =
+            apply_with_retries(backend, pool, source, settings, None, shutdown).await;
+        with_backup(report, backup)
     };
+    let report = report.await;
+    lock.release().await;
+    report
+}
 
-    let migrations_files = match get_migrations_files(folder_path).await {
-        Ok(m) => m,
-        Err(err) => {
-            error!(
-                function = "get_migrations_files",
-                error_message = format!("{err}"),
-                message = "Could not get migrations files",
+pub(crate) async fn run_migrate_to(
+    backend: Backend,
+    pool: &AnyPool,
+    source: Source<'_>,
+    settings: &Settings,
+    target: &str,
+) -> Result<MigrationReport, AromaticError> {
+    settings
+        .open_transactions
+        .guard(backend, pool, settings.lock_timeout)
+        .await?;
+    let lock =
+        MigrationLock::acquire(backend, pool, &settings.table, settings.lock_timeout)
+            .await?;
+    let report = async {
+        let backup = backup_before_run(backend, pool, source, settings).await?;
+        let report = migrate_to_target(backend, pool, source, settings, target).await;
+        with_backup(report, backup)
+    };
+    let report = report.await;
+    lock.release().await;
+    report
+}
+
+/// Copies the SQLite database when `BACKUP_BEFORE_MIGRATIONS` is set and the run has
+/// something to apply.
+async fn backup_before_run(
+    backend: Backend,
+    pool: &AnyPool,
+    source: Source<'_>,
+    settings: &Settings,
+) -> Result<Option<PathBuf>, AromaticError> {
+    if !settings.backup || settings.dry_run || backend != Backend::Sqlite {
+        return Ok(None);
+    }
+    let pending = run_status(backend, pool, source, settings)
+        .await?
+        .iter()
+        .any(|status| status.state == MigrationState::Pending);
+    match pending {
+        true => backup_sqlite(pool).await.map(Some),
+        false => Ok(None),
+    }
+}
+
+fn with_backup(
+    report: Result<MigrationReport, AromaticError>,
+    backup: Option<PathBuf>,
+) -> Result<MigrationReport, AromaticError> {
+    match (report, backup) {
+        (Ok(report), backup) => Ok(MigrationReport { backup, ..report }),
+        (Err(err), Some(backup)) => {
+            warn!(
+                function = "with_backup",
+                message = format!(
+                    "The run failed, the database was backed up to {} before it",
+                    backup.display()
+                ),
             );
-            return;
+            Err(err)
         },
-    };
+        (Err(err), None) => Err(err),
+    }
+}
+
+async fn migrate_to_target(
+    backend: Backend,
+    pool: &AnyPool,
+    source: Source<'_>,
+    settings: &Settings,
+    target: &str,
+) -> Result<MigrationReport, AromaticError> {
+    let newer: HashSet<String> = get_migrations_files(source, settings)
+        .await?
+        .into_iter()
+        .map(|f| f.name)
+        .skip_while(|name| name != target)
+        .skip(1)
+        .collect();
+    let reverted = revert_migrations(backend, pool, source, settings, |applied| {
+        applied
+            .into_iter()
+            .filter(|m| newer.contains(&m.name))
+            .collect()
+    })
+    .await?;
+    let shutdown = AtomicBool::new(false);
+    let mut report =
+        apply_with_retries(backend, pool, source, settings, Some(target), &shutdown)
+            .await?;
+    report.reverted = reverted;
+    Ok(report)
+}
+
+async fn apply_with_retries(
+    backend: Backend,
+    pool: &AnyPool,
+    source: Source<'_>,
+    settings: &Settings,
+    target: Option<&str>,
+    shutdown: &AtomicBool,
+) -> Result<MigrationReport, AromaticError> {
+    let mut attempt = 0;
+    loop {
+        match apply_migrations(backend, pool, source, settings, target, shutdown).await {
+            Err(err) if err.is_transient() && attempt < settings.retries => {
+                attempt += 1;
+                warn!(
+                    function = "apply_with_retries",
+                    error_message = format!("{err}"),
+                    message = format!("Transient failure, retrying the run ({attempt})"),
+                );
+                tokio::time::sleep(RETRY_BACKOFF * attempt).await;
+            },
+            result => break result,
+        }
+    }
+}
+
+/// Times a run failing with a serialization failure or a deadlock is started over,
+/// from `MIGRATION_RETRIES`. Migrations committed before the failure are skipped by
+/// the next attempt, so with a transaction per file only the rest is retried.
+fn migration_retries() -> u32 {
+    std::env::var("MIGRATION_RETRIES")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(DEFAULT_MIGRATION_RETRIES)
+}
+
+/// Extensions of the files of the migrations folder that are migrations, from
+/// `MIGRATION_EXTENSIONS` separated by commas, `sql` by default.
+fn migration_extensions() -> Vec<String> {
+    let extensions: Vec<String> = std::env::var("MIGRATION_EXTENSIONS")
+        .unwrap_or_default()
+        .split(',')
+        .map(|extension| extension.trim().trim_start_matches('.').to_string())
+        .filter(|extension| !extension.is_empty())
+        .collect();
+    match extensions.is_empty() {
+        true => vec![DEFAULT_EXTENSION.to_string()],
+        false => extensions,
+    }
+}
+
+/// Transactions the migrations of a run go through. By default each file is committed
+/// along with its history row before the next one starts, so a failure only rolls
+/// back the file that failed. With `SINGLE_TRANSACTION_MIGRATIONS` set, or in a dry
+/// run, everything is committed or rolled back at the end.
+struct Session<'a> {
+    pool: &'a AnyPool,
+    settings: &'a Settings,
+    transaction: Option<Transaction<'static, Any>>,
+}
+
+impl<'a> Session<'a> {
+    fn new(pool: &'a AnyPool, settings: &'a Settings) -> Self {
+        Self {
+            pool,
+            settings,
+            transaction: None,
+        }
+    }
+
+    /// The open transaction, started if there is none.
+    async fn connection(&mut self) -> Result<&mut AnyConnection, sqlx::Error> {
+        let transaction = match self.transaction.take() {
+            Some(transaction) => transaction,
+            None => transaction(self.pool).await?,
+        };
+        Ok(self.transaction.insert(transaction))
+    }
+
+    /// Commits the work done so far unless the run is a single transaction.
+    async fn checkpoint(&mut self) -> Result<(), sqlx::Error> {
+        match self.settings.single_transaction || self.settings.dry_run {
+            true => Ok(()),
+            false => self.commit().await,
+        }
+    }
+
+    async fn commit(&mut self) -> Result<(), sqlx::Error> {
+        match self.transaction.take() {
+            Some(transaction) => commit_transaction(transaction).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Runs `query` on its own connection, after committing what is pending so it
+    /// doesn't wait on locks held by the open transaction.
+    async fn execute_outside(
+        &mut self,
+        backend: Backend,
+        query: &str,
+    ) -> Result<u64, StatementError> {
+        self.commit().await?;
+        let mut connection = self.pool.acquire().await?;
+        execute_migration(backend, query, &mut connection).await
+    }
+
+    /// Discards the open transaction.
+    async fn rollback(&mut self) -> Result<(), sqlx::Error> {
+        match self.transaction.take() {
+            Some(transaction) => rollback_transaction(transaction).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn finish(mut self) -> Result<(), sqlx::Error> {
+        match (self.settings.dry_run, self.transaction.take()) {
+            (true, Some(transaction)) => rollback_transaction(transaction).await,
+            (false, Some(transaction)) => commit_transaction(transaction).await,
+            (_, None) => Ok(()),
+        }
+    }
+}
+
+async fn apply_migrations(
+    backend: Backend,
+    pool: &AnyPool,
+    source: Source<'_>,
+    settings: &Settings,
+    target: Option<&str>,
+    shutdown: &AtomicBool,
+) -> Result<MigrationReport, AromaticError> {
+    let table = &settings.table;
+    let mut session = Session::new(pool, settings);
+    create_migrations_table(backend, table, session.connection().await?)
+        .await
+        .map_err(AromaticError::History)?;
+    let migrations_history =
+        get_migrations_history(backend, table, session.connection().await?)
+            .await
+            .map_err(AromaticError::History)?;
+    session.checkpoint().await?;
+    let mut migrations_files = get_migrations_files(source, settings).await?;
+    if let Some(target) = target {
+        let position = migrations_files
+            .iter()
+            .position(|f| f.name == target)
+            .ok_or_else(|| AromaticError::UnknownMigration(target.to_string()))?;
+        migrations_files.truncate(position + 1);
+    }
+
     // maybe just loop over all the files migrations, save them into the database if they don0t exists.
     // then query the database to get the list of migrations and execute them.
-    match migrations_history.is_empty() {
-        true => run_inital_migrations(migrations_files, &mut transaction).await,
+    let report = match migrations_history.is_empty() {
+        true => {
+            run_inital_migrations(backend, migrations_files, &mut session, shutdown)
+                .await?
+        },
         false => {
-            run_migrations(migrations_files, migrations_history, &mut transaction).await
+            run_migrations(
+                backend,
+                migrations_files,
+                migrations_history,
+                &mut session,
+                shutdown,
+            )
+            .await?
         },
+    };
+    session.finish().await?;
+    let check_run = settings.integrity_checks == IntegrityChecks::Fail
+        && backend == Backend::Sqlite
+        && !settings.dry_run;
+    if let (true, Some(last)) = (check_run, report.applied.last()) {
+        let problems = integrity_problems(&mut *pool.acquire().await?).await?;
+        if !problems.is_empty() {
+            return Err(AromaticError::IntegrityCheck {
+                after: last.clone(),
+                problems,
+            });
+        }
     }
-    match commit_transaction(transaction).await {
-        Ok(_) => (),
-        Err(err) => error!(
-            function = "commit_transaction",
-            error_message = format!("{err}"),
-            message = "Could not commit migrations",
-        ),
+    Ok(report)
+}
+
+/// Compares the files in `folder_path` with the history without applying anything.
+/// Files come first in the order they would run, then the applied migrations whose
+/// file no longer exists.
+pub async fn migration_status(
+    folder_path: &str,
+) -> Result<Vec<MigrationStatus>, AromaticError> {
+    Migrator::builder()
+        .folder(folder_path)
+        .build()
+        .status()
+        .await
+}
+
+pub(crate) async fn run_status(
+    backend: Backend,
+    pool: &AnyPool,
+    source: Source<'_>,
+    settings: &Settings,
+) -> Result<Vec<MigrationStatus>, AromaticError> {
+    let mut transaction = transaction(pool).await?;
+    create_migrations_table(backend, &settings.table, &mut transaction)
+        .await
+        .map_err(AromaticError::History)?;
+    let history = get_migrations_history(backend, &settings.table, &mut transaction)
+        .await
+        .map_err(AromaticError::History)?;
+    rollback_transaction(transaction).await?;
+    let files = get_migrations_files(source, settings).await?;
+    Ok(compare_with_history(files, history))
+}
+
+/// Parses the pending migrations of `folder_path` without running them, so a syntax
+/// error in any of them is reported before the run starts. Returns their names,
+/// the error lists every file that doesn't parse. Migrations written in Rust are
+/// not checked.
+pub async fn validate(folder_path: &str) -> Result<Vec<String>, AromaticError> {
+    Migrator::builder()
+        .folder(folder_path)
+        .build()
+        .validate()
+        .await
+}
+
+pub(crate) async fn run_validate(
+    backend: Backend,
+    pool: &AnyPool,
+    source: Source<'_>,
+    settings: &Settings,
+) -> Result<Vec<String>, AromaticError> {
+    let pending: HashSet<String> = run_status(backend, pool, source, settings)
+        .await?
+        .into_iter()
+        .filter(|status| status.state == MigrationState::Pending)
+        .map(|status| status.name)
+        .collect();
+    let mut validated = Vec::new();
+    let mut errors = Vec::new();
+    for migration_file in get_migrations_files(source, settings).await? {
+        if !pending.contains(&migration_file.name) || migration_file.code.is_some() {
+            continue;
+        }
+        let sql = read_migration(&migration_file).await?;
+        for part in [Some(&sql.up), sql.down.as_ref()].into_iter().flatten() {
+            if let Err(err) = backend.parse_sql(part) {
+                errors.push(format!("{}: {err}", migration_file.name));
+            }
+        }
+        validated.push(migration_file.name);
+    }
+    match errors.is_empty() {
+        true => Ok(validated),
+        false => Err(AromaticError::InvalidSql(errors.join("\n"))),
+    }
+}
+
+/// Every row of the history table, oldest first, including the migrations that were
+/// reverted or whose last run failed.
+pub async fn migration_history() -> Result<Vec<HistoryRecord>, AromaticError> {
+    Migrator::builder().build().history().await
+}
+
+pub(crate) async fn run_history(
+    backend: Backend,
+    pool: &AnyPool,
+    settings: &Settings,
+) -> Result<Vec<HistoryRecord>, AromaticError> {
+    let table = &settings.table;
+    let mut transaction = transaction(pool).await?;
+    create_migrations_table(backend, table, &mut transaction)
+        .await
+        .map_err(AromaticError::History)?;
+    let columns = format!(
+        "{} AS name, CASE WHEN ran THEN 1 ELSE 0 END AS ran, {} AS timestamp, \
+         {} AS duration_ms, {} AS checksum, {} AS applied_by, {} AS app_version, \
+         {} AS error",
+        backend.cast_to_text("name"),
+        backend.cast_to_text("timestamp"),
+        backend.cast_to_text("duration_ms"),
+        backend.cast_to_text("checksum"),
+        backend.cast_to_text("applied_by"),
+        backend.cast_to_text("app_version"),
+        backend.cast_to_text("error"),
+    );
+    let query = Orm::select(&columns)
+        .from(&table.quoted(backend))
+        .order_by("id")
+        .ready();
+    let records = sqlx::query_as::<_, HistoryRecord>(&query)
+        .fetch_all(&mut *transaction)
+        .await
+        .map_err(AromaticError::History)?;
+    rollback_transaction(transaction).await?;
+    Ok(records)
+}
+
+fn compare_with_history(
+    files: Vec<MigrationFile>,
+    history: Vec<Migration>,
+) -> Vec<MigrationStatus> {
+    let mut history: HashMap<String, Migration> =
+        history.into_iter().map(|m| (m.name.clone(), m)).collect();
+    let mut statuses: Vec<MigrationStatus> = files
+        .into_iter()
+        .map(|file| match history.remove(&file.name) {
+            Some(migration) if migration.ran => MigrationStatus {
+                name: file.name,
+                state: MigrationState::Applied,
+                applied_at: Some(migration.timestamp),
+            },
+            _ => MigrationStatus {
+                name: file.name,
+                state: MigrationState::Pending,
+                applied_at: None,
+            },
+        })
+        .collect();
+    let mut missing: Vec<Migration> = history.into_values().filter(|m| m.ran).collect();
+    missing.sort_by_key(|m| m.id);
+    statuses.extend(missing.into_iter().map(|migration| MigrationStatus {
+        name: migration.name,
+        state: MigrationState::MissingOnDisk,
+        applied_at: Some(migration.timestamp),
+    }));
+    statuses
+}
+
+/// Reverts the last `steps` applied migrations, newest first, inside a single
+/// transaction and returns their names. The down SQL recorded in the history is
+/// used, falling back to the files in `folder_path` for migrations applied before it
+/// was recorded. If any of them can't be reverted nothing is.
+pub async fn rollback(
+    folder_path: &str,
+    steps: u32,
+) -> Result<Vec<String>, AromaticError> {
+    Migrator::builder()
+        .folder(folder_path)
+        .build()
+        .rollback(steps)
+        .await
+}
+
+/// Records the migrations up to and including `up_to` as applied without running
+/// them, to adopt aromatic on a database whose schema already has them. Migrations
+/// already applied are left as they are. Returns the names recorded.
+pub async fn baseline(
+    folder_path: &str,
+    up_to: &str,
+) -> Result<Vec<String>, AromaticError> {
+    Migrator::builder()
+        .folder(folder_path)
+        .build()
+        .baseline(up_to)
+        .await
+}
+
+pub(crate) async fn run_baseline(
+    backend: Backend,
+    pool: &AnyPool,
+    source: Source<'_>,
+    settings: &Settings,
+    up_to: &str,
+) -> Result<Vec<String>, AromaticError> {
+    let lock =
+        MigrationLock::acquire(backend, pool, &settings.table, settings.lock_timeout)
+            .await?;
+    let recorded =
+        record_as_applied(backend, pool, source, settings, |mut migrations_files| {
+            let position = migrations_files
+                .iter()
+                .position(|f| f.name == up_to)
+                .ok_or_else(|| AromaticError::UnknownMigration(up_to.to_string()))?;
+            migrations_files.truncate(position + 1);
+            Ok(migrations_files)
+        })
+        .await;
+    lock.release().await;
+    recorded
+}
+
+/// Records the migrations `names` as applied without running them, for changes that
+/// were made by hand, in an emergency for instance. Migrations already applied are
+/// left as they are. Returns the names recorded.
+pub async fn fake(
+    folder_path: &str,
+    names: &[&str],
+) -> Result<Vec<String>, AromaticError> {
+    Migrator::builder()
+        .folder(folder_path)
+        .build()
+        .fake(names)
+        .await
+}
+
+pub(crate) async fn run_fake(
+    backend: Backend,
+    pool: &AnyPool,
+    source: Source<'_>,
+    settings: &Settings,
+    names: &[&str],
+) -> Result<Vec<String>, AromaticError> {
+    let lock =
+        MigrationLock::acquire(backend, pool, &settings.table, settings.lock_timeout)
+            .await?;
+    let recorded =
+        record_as_applied(backend, pool, source, settings, |migrations_files| {
+            if let Some(unknown) = names
+                .iter()
+                .find(|name| !migrations_files.iter().any(|f| f.name == **name))
+            {
+                return Err(AromaticError::UnknownMigration(unknown.to_string()));
+            }
+            Ok(migrations_files
+                .into_iter()
+                .filter(|f| names.contains(&f.name.as_str()))
+                .collect())
+        })
+        .await;
+    lock.release().await;
+    recorded
+}
+
+/// Records the files `select` picks as applied, without running them.
+async fn record_as_applied(
+    backend: Backend,
+    pool: &AnyPool,
+    source: Source<'_>,
+    settings: &Settings,
+    select: impl FnOnce(Vec<MigrationFile>) -> Result<Vec<MigrationFile>, AromaticError>,
+) -> Result<Vec<String>, AromaticError> {
+    let table = &settings.table;
+    let migrations_files = select(get_migrations_files(source, settings).await?)?;
+
+    let mut transaction = transaction(pool).await?;
+    create_migrations_table(backend, table, &mut transaction)
+        .await
+        .map_err(AromaticError::History)?;
+    let history: HashMap<String, Migration> =
+        get_migrations_history(backend, table, &mut transaction)
+            .await
+            .map_err(AromaticError::History)?
+            .into_iter()
+            .map(|m| (m.name.clone(), m))
+            .collect();
+    let audit = Audit::not_run(settings);
+    let mut recorded = Vec::new();
+    for mut migration_file in migrations_files {
+        let id_to_update = match history.get(&migration_file.name) {
+            Some(migration) if migration.ran => continue,
+            Some(migration) => Some(migration.id),
+            None => None,
+        };
+        let sql = read_migration(&migration_file).await?;
+        migration_file.ran = true;
+        save_or_update(
+            backend,
+            table,
+            &mut migration_file,
+            &sql,
+            &audit,
+            &mut transaction,
+            id_to_update,
+        )
+        .await?;
+        recorded.push(migration_file.name);
+    }
+    match settings.dry_run {
+        true => rollback_transaction(transaction).await?,
+        false => commit_transaction(transaction).await?,
+    }
+    Ok(recorded)
+}
+
+pub(crate) async fn run_rollback(
+    backend: Backend,
+    pool: &AnyPool,
+    source: Source<'_>,
+    settings: &Settings,
+    steps: u32,
+) -> Result<Vec<String>, AromaticError> {
+    settings
+        .open_transactions
+        .guard(backend, pool, settings.lock_timeout)
+        .await?;
+    let lock =
+        MigrationLock::acquire(backend, pool, &settings.table, settings.lock_timeout)
+            .await?;
+    let reverted = revert_migrations(backend, pool, source, settings, |applied| {
+        applied.into_iter().take(steps as usize).collect()
+    })
+    .await;
+    lock.release().await;
+    reverted
+}
+
+/// Reverts the last `steps` applied migrations like [`rollback`] and applies them
+/// again, to iterate on a migration while writing it.
+pub async fn redo(
+    folder_path: &str,
+    steps: u32,
+) -> Result<MigrationReport, AromaticError> {
+    Migrator::builder()
+        .folder(folder_path)
+        .build()
+        .redo(steps)
+        .await
+}
+
+pub(crate) async fn run_redo(
+    backend: Backend,
+    pool: &AnyPool,
+    source: Source<'_>,
+    settings: &Settings,
+    steps: u32,
+) -> Result<MigrationReport, AromaticError> {
+    settings
+        .open_transactions
+        .guard(backend, pool, settings.lock_timeout)
+        .await?;
+    let lock =
+        MigrationLock::acquire(backend, pool, &settings.table, settings.lock_timeout)
+            .await?;
+    let report = redo_migrations(backend, pool, source, settings, steps).await;
+    lock.release().await;
+    report
+}
+
+async fn redo_migrations(
+    backend: Backend,
+    pool: &AnyPool,
+    source: Source<'_>,
+    settings: &Settings,
+    steps: u32,
+) -> Result<MigrationReport, AromaticError> {
+    let reverted = revert_migrations(backend, pool, source, settings, |applied| {
+        applied.into_iter().take(steps as usize).collect()
+    })
+    .await?;
+    // newest first, so the first one is where the re-apply stops
+    let Some(target) = reverted.first() else {
+        return Ok(MigrationReport::default());
     };
+    let shutdown = AtomicBool::new(false);
+    let mut report =
+        apply_with_retries(backend, pool, source, settings, Some(target), &shutdown)
+            .await?;
+    report.reverted = reverted;
+    Ok(report)
 }
 
-async fn create_database(db_url: &str) {
-    match Sqlite::create_database(db_url).await {
+/// Removes the migrations lock left by a runner that was killed before releasing
+/// it. Returns whether there was one.
+pub async fn force_unlock() -> Result<bool, AromaticError> {
+    let (db_url, backend) = migration_database_url()?;
+    let pool = connect(&db_url).await?;
+    clear_lock(backend, &pool, &HistoryTable::from_env()).await
+}
+
+/// Reverts the migrations `select` picks from the applied ones, given newest first.
+async fn revert_migrations(
+    backend: Backend,
+    pool: &AnyPool,
+    source: Source<'_>,
+    settings: &Settings,
+    select: impl FnOnce(Vec<Migration>) -> Vec<Migration>,
+) -> Result<Vec<String>, AromaticError> {
+    let table = &settings.table;
+    let mut transaction = transaction(pool).await?;
+    create_migrations_table(backend, table, &mut transaction)
+        .await
+        .map_err(AromaticError::History)?;
+    let applied = get_applied_migrations(backend, table, &mut transaction)
+        .await
+        .map_err(AromaticError::History)?;
+    let applied = select(applied);
+
+    let mut reverted = Vec::with_capacity(applied.len());
+    for migration in applied {
+        let code = settings
+            .code_migrations
+            .iter()
+            .find(|code| code.name() == migration.name);
+        let result = match code {
+            Some(code) => match code.down(&mut transaction) {
+                Some(down) => down.await.map(|()| 0),
+                None => return Err(AromaticError::MissingDown(migration.name)),
+            },
+            None => {
+                let down = down_sql(&migration, source, settings)
+                    .await?
+                    .ok_or_else(|| AromaticError::MissingDown(migration.name.clone()))?;
+                execute_migration(backend, &down, &mut transaction)
+                    .await
+                    .map_err(|err| err.source)
+            },
+        };
+        result.map_err(|source| AromaticError::Migration {
+            name: migration.name.clone(),
+            path: PathBuf::from(&migration.path),
+            location: None,
+            source,
+        })?;
+        mark_as_reverted(backend, table, &mut transaction, migration.id)
+            .await
+            .map_err(AromaticError::History)?;
+        reverted.push(migration.name);
+    }
+    commit_transaction(transaction).await?;
+    Ok(reverted)
+}
+
+async fn down_sql(
+    migration: &Migration,
+    source: Source<'_>,
+    settings: &Settings,
+) -> Result<Option<String>, AromaticError> {
+    if migration.down.is_some() {
+        return Ok(migration.down.clone());
+    }
+    let migration_file = get_migrations_files(source, settings)
+        .await?
+        .into_iter()
+        .find(|f| f.name == migration.name);
+    match migration_file {
+        Some(migration_file) => Ok(read_migration(&migration_file).await?.down),
+        None => Ok(None),
+    }
+}
+
+pub(crate) fn database_url() -> Result<(String, Backend), AromaticError> {
+    let db_url = resolve_database_url(None)?
+        .ok_or_else(|| AromaticError::Config("DATABASE_URL is not set".to_string()))?;
+    match Backend::from_url(&db_url.value) {
+        Some(backend) => Ok((db_url.value, backend)),
+        None => Err(AromaticError::Config(format!(
+            "DATABASE_URL from {} does not point to a supported database",
+            db_url.source
+        ))),
+    }
+}
+
+/// Database the migrations run on: `MIGRATION_DATABASE_URL`, then
+/// `migration_database_url` in `aromatic.toml`, so they can use a role with DDL rights
+/// the application doesn't have. Falls back to [`database_url`].
+pub(crate) fn migration_database_url() -> Result<(String, Backend), AromaticError> {
+    let db_url = match std::env::var("MIGRATION_DATABASE_URL") {
+        Ok(value) if !value.is_empty() => Some(value),
+        _ => find_file_config()?.and_then(|(_, config)| config.migration_database_url),
+    };
+    let Some(db_url) = db_url else {
+        return database_url();
+    };
+    match Backend::from_url(&db_url) {
+        Some(backend) => Ok((db_url, backend)),
+        None => Err(AromaticError::Config(
+            "MIGRATION_DATABASE_URL does not point to a supported database".to_string(),
+        )),
+    }
+}
+
+pub(crate) async fn create_database(db_url: &str) {
+    install_default_drivers();
+    match Any::create_database(db_url).await {
         Ok(_) => (),
         Err(err) => {
             error!(
@@ -123,243 +1405,966 @@ async fn create_database(db_url: &str) {
     };
 }
 
-async fn create_migrations_table<'a>(
-    transaction: &mut Transaction<'a, Sqlite>,
+async fn create_migrations_table(
+    backend: Backend,
+    table: &HistoryTable,
+    connection: &mut AnyConnection,
 ) -> Result<u64, sqlx::Error> {
-    let query = r#"
-        CREATE TABLE IF NOT EXISTS migrations (
-            id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
-            name TEXT NOT NULL,
-            path TEXT NOT NULL,
-            ran BOOLEAN NOT NULL,
-            timestamp TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+    let result = sqlx::query(&backend.migrations_table_ddl(&table.quoted(backend)))
+        .execute(&mut *connection)
+        .await?;
+    add_missing_history_columns(backend, table, connection).await?;
+    Ok(result.rows_affected())
+}
+
+const DEFAULT_HISTORY_TABLE: &str = "migrations";
+
+/// The history table, from `MIGRATIONS_TABLE` so services sharing a database can keep
+/// separate histories. It can be qualified with a schema, `billing.migrations`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct HistoryTable {
+    schema: Option<String>,
+    name: String,
+}
+
+impl HistoryTable {
+    pub(crate) fn from_env() -> Self {
+        let value = std::env::var("MIGRATIONS_TABLE")
+            .ok()
+            .filter(|value| !value.is_empty());
+        Self::parse(value.as_deref().unwrap_or(DEFAULT_HISTORY_TABLE))
+    }
+
+    pub(crate) fn parse(value: &str) -> Self {
+        match value.split_once('.') {
+            Some((schema, name)) => Self {
+                schema: Some(schema.to_string()),
+                name: name.to_string(),
+            },
+            None => Self {
+                schema: None,
+                name: value.to_string(),
+            },
+        }
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Table of the migrations lock, in the same schema, `migrations_lock` for
+    /// `migrations`.
+    pub(crate) fn lock_table(&self) -> Self {
+        Self {
+            schema: self.schema.clone(),
+            name: format!("{}_lock", self.name),
+        }
+    }
+
+    pub(crate) fn quoted(&self, backend: Backend) -> String {
+        let name = backend.quote_identifier(&self.name);
+        match &self.schema {
+            Some(schema) => format!("{}.{name}", backend.quote_identifier(schema)),
+            None => name,
+        }
+    }
+}
+
+/// Columns added to the history table after its first release, so tables created by
+/// older versions get upgraded in place.
+const HISTORY_COLUMNS: [(&str, &str); 7] = [
+    ("down", "TEXT"),
+    ("checksum", "TEXT"),
+    ("duration_ms", "BIGINT"),
+    ("applied_by", "TEXT"),
+    ("app_version", "TEXT"),
+    ("error", "TEXT"),
+    ("namespace", "TEXT"),
+];
+
+async fn add_missing_history_columns(
+    backend: Backend,
+    table: &HistoryTable,
+    connection: &mut AnyConnection,
+) -> Result<(), sqlx::Error> {
+    let columns_query = backend.columns_query(table.schema.as_deref(), &table.name);
+    let existing: Vec<String> = sqlx::query_scalar(&columns_query)
+        .fetch_all(&mut *connection)
+        .await?;
+    let quoted = table.quoted(backend);
+    for (column, data_type) in HISTORY_COLUMNS {
+        if existing.iter().any(|c| c == column) {
+            continue;
+        }
+        sqlx::query(&format!(
+            "ALTER TABLE {quoted} ADD COLUMN {column} {data_type};"
+        ))
+        .execute(&mut *connection)
+        .await?;
+    }
+    Ok(())
+}
+
+async fn get_migrations_history(
+    backend: Backend,
+    table: &HistoryTable,
+    connection: &mut AnyConnection,
+) -> Result<Vec<Migration>, sqlx::Error> {
+    let query = Orm::select(&migration_columns(backend))
+        .from(&table.quoted(backend))
+        .ready();
+    let rows = sqlx::query_as::<_, Migration>(&query)
+        .fetch_all(&mut *connection)
+        .await;
+
+    match rows {
+        Ok(result) => Ok(result),
+        Err(err) => {
+            error!(
+                function = "get_migrations_history",
+                error_message = format!("{err}"),
+                message = "Error finding the migration",
+            );
+            Err(err)
+        },
+    }
+}
+
+async fn get_applied_migrations(
+    backend: Backend,
+    table: &HistoryTable,
+    connection: &mut AnyConnection,
+) -> Result<Vec<Migration>, sqlx::Error> {
+    let query = Orm::select(&migration_columns(backend))
+        .from(&table.quoted(backend))
+        .where_()
+        .condition("ran = true")
+        .order_by("id DESC")
+        .ready();
+    sqlx::query_as::<_, Migration>(&query)
+        .fetch_all(&mut *connection)
+        .await
+}
+
+/// The migrations of `source`, of the namespaced sources and the code migrations,
+/// merged in version order.
+async fn get_migrations_files(
+    source: Source<'_>,
+    settings: &Settings,
+) -> Result<Vec<MigrationFile>, AromaticError> {
+    let mut migrations_files = read_source(source, settings).await?;
+    for (namespace, source) in &settings.namespaces {
+        let files = read_source(source.as_source(), settings).await?;
+        migrations_files.extend(files.into_iter().map(|f| f.namespaced(namespace)));
+    }
+    migrations_files.extend(settings.code_migrations.iter().map(MigrationFile::code));
+    sort_migrations_files(migrations_files, settings.allow_unversioned)
+}
+
+/// The migrations of `source` along with those of its test folder.
+async fn read_source(
+    source: Source<'_>,
+    settings: &Settings,
+) -> Result<Vec<MigrationFile>, AromaticError> {
+    let mut migrations_files = read_migrations_files(source, settings).await?;
+    if let (Source::Folder(folder_path), TestMarker::Folder(test_folder), false) =
+        (source, &settings.test_marker, settings.recursive)
+    {
+        let test_folder = Path::new(folder_path).join(test_folder);
+        if test_folder.is_dir() {
+            let test_folder = test_folder.display().to_string();
+            migrations_files.extend(
+                read_migrations_files(Source::Folder(&test_folder), settings).await?,
+            );
+        }
+    }
+    Ok(migrations_files)
+}
+
+/// The migrations of the folder, or of it and its subfolders when `recursive` is set.
+/// Only the files with one of the `extensions` count, so hidden files, editor swap
+/// files or a `.gitkeep` are left out.
+async fn read_migrations_files(
+    source: Source<'_>,
+    settings: &Settings,
+) -> Result<Vec<MigrationFile>, AromaticError> {
+    let folder_path = match source {
+        Source::Folder(folder_path) => folder_path,
+        Source::Embedded(migrations) => {
+            let migrations_files = migrations
+                .iter()
+                .filter(|m| !m.name.ends_with(DOWN_SUFFIX))
+                .map(|m| MigrationFile::embedded(m, migrations))
+                .collect();
+            return Ok(migrations_files);
+        },
+    };
+    let mut migrations_files = Vec::new();
+    let mut folders = vec![PathBuf::from(folder_path)];
+    while let Some(folder) = folders.pop() {
+        let (paths, subfolders) = match scan_folder(&folder).await {
+            Ok(entries) => entries,
+            Err(err) if folder != Path::new(folder_path) => {
+                warn!(
+                    function = "get_migrations_files",
+                    error_message = format!("{err}"),
+                    message = format!("Skipping {}, it can't be read", folder.display()),
+                );
+                continue;
+            },
+            Err(err) => {
+                error!(
+                    function = "get_migrations_files",
+                    error_message = format!("{err}"),
+                    message = "error reading dir",
+                );
+                return Err(AromaticError::io(folder_path, err));
+            },
+        };
+        if settings.recursive {
+            folders.extend(subfolders);
+        }
+        let paths: Vec<PathBuf> = paths
+            .into_iter()
+            .filter(|path| is_migration_path(path, &settings.extensions))
+            .collect();
+        let names: HashSet<String> = paths
+            .iter()
+            .filter_map(|path| path.file_name())
+            .map(|name| name.to_string_lossy().to_string())
+            .collect();
+        migrations_files.extend(
+            paths
+                .into_iter()
+                .map(|path| MigrationFile::new(path, &names))
+                .filter(|f| !f.name.ends_with(DOWN_SUFFIX)),
         );
-    "#;
-    let result = sqlx::query(query)
-        .execute(transaction as &mut SqliteConnection)
-        .await?;
-    Ok(result.rows_affected())
+    }
+    Ok(migrations_files)
 }
 
-async fn get_migrations_history<'a>(
-    transaction: &mut Transaction<'a, Sqlite>,
-) -> Result<Vec<Migration>, sqlx::Error> {
-    let query = Orm::select("*").from("migrations").ready();
-    let rows = sqlx::query_as::<_, Migration>(&query)
-        .fetch_all(transaction as &mut SqliteConnection)
-        .await;
+fn is_migration_path(path: &Path, extensions: &[String]) -> bool {
+    let hidden = path
+        .file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+    let extension = path.extension().map(|ext| ext.to_string_lossy());
+    !hidden && extension.is_some_and(|ext| extensions.iter().any(|e| *e == ext))
+}
 
-    match rows {
-        Ok(result) => Ok(result),
-        Err(err) => {
-            error!(
-                function = "get_migrations_history",
-                error_message = format!("{err}"),
-                message = "Error finding the migration",
+/// The files and the subfolders of `folder`. Entries that can't be read are logged and
+/// skipped rather than failing the whole listing. Symlinked folders are skipped too,
+/// one pointing to an ancestor would list the same migrations over and over.
+async fn scan_folder(
+    folder: &Path,
+) -> Result<(Vec<PathBuf>, Vec<PathBuf>), std::io::Error> {
+    let mut entries = tokio::fs::read_dir(folder).await?;
+    let (mut files, mut folders) = (Vec::new(), Vec::new());
+    loop {
+        let entry = match entries.next_entry().await {
+            Ok(Some(entry)) => entry,
+            Ok(None) => break,
+            Err(err) => {
+                warn!(
+                    function = "scan_folder",
+                    error_message = format!("{err}"),
+                    message = format!("Skipping an entry of {}", folder.display()),
+                );
+                continue;
+            },
+        };
+        let path = entry.path();
+        let file_type = match entry.file_type().await {
+            Ok(file_type) => file_type,
+            Err(err) => {
+                warn!(
+                    function = "scan_folder",
+                    error_message = format!("{err}"),
+                    message = format!("Skipping {}", path.display()),
+                );
+                continue;
+            },
+        };
+        if file_type.is_dir() {
+            folders.push(path);
+        } else if !file_type.is_symlink() {
+            files.push(path);
+        } else if tokio::fs::metadata(&path).await.is_ok_and(|m| m.is_dir()) {
+            warn!(
+                function = "scan_folder",
+                message =
+                    format!("Not following the symlinked folder {}", path.display()),
             );
-            Err(err)
-        },
+        } else {
+            files.push(path);
+        }
     }
+    Ok((files, folders))
 }
 
-async fn get_migrations_files(
+/// Paths of the entries of `folder_path`, listed without blocking the runtime.
+pub(crate) async fn read_folder(
     folder_path: &str,
-) -> Result<Vec<MigrationFile>, std::io::Error> {
-    let entries = match read_dir(folder_path) {
-        Ok(result) => result,
-        Err(err) => {
-            error!(
-                function = "get_migrations_history",
-                error_message = format!("{err}"),
-                message = "error reading dir",
-            );
-            return Err(err);
-        },
-    };
+) -> Result<Vec<PathBuf>, std::io::Error> {
+    let mut entries = tokio::fs::read_dir(folder_path).await?;
+    let mut paths = Vec::new();
+    while let Some(entry) = entries.next_entry().await? {
+        paths.push(entry.path());
+    }
+    Ok(paths)
+}
 
-    Ok(entries
-        .into_iter()
-        .map(|f| MigrationFile::new(f.ok().unwrap()))
-        .collect())
+/// Orders the files by their version prefix, as `read_dir` order depends on the
+/// filesystem. Files without one are rejected unless `allow_unversioned` is set, then
+/// they run after the versioned ones in name order. Files with the same version and
+/// sequence fall back to name order, which is stable but likely not intended so it
+/// is logged unless they come from different namespaces.
+fn sort_migrations_files(
+    mut migrations_files: Vec<MigrationFile>,
+    allow_unversioned: bool,
+) -> Result<Vec<MigrationFile>, AromaticError> {
+    if !allow_unversioned {
+        if let Some(file) = migrations_files.iter().find(|f| f.version.is_none()) {
+            return Err(AromaticError::UnversionedMigration(file.name.clone()));
+        }
+    }
+    migrations_files.sort_by(|a, b| {
+        (a.version.is_none(), a.version, &a.name).cmp(&(
+            b.version.is_none(),
+            b.version,
+            &b.name,
+        ))
+    });
+    for pair in migrations_files.windows(2) {
+        if pair[0].version.is_some()
+            && pair[0].version == pair[1].version
+            && pair[0].namespace == pair[1].namespace
+        {
+            warn!(
+                function = "sort_migrations_files",
+                message = format!(
+                    "{} and {} share the same version, add a -N sequence to order them",
+                    pair[0].name, pair[1].name
+                ),
+            );
+        }
+    }
+    Ok(migrations_files)
 }
 
-async fn run_migrations<'a>(
+async fn run_migrations(
+    backend: Backend,
     migrations_files: Vec<MigrationFile>,
     migrations_history: Vec<Migration>,
-    transaction: &mut Transaction<'a, Sqlite>,
-) {
+    session: &mut Session<'_>,
+    shutdown: &AtomicBool,
+) -> Result<MigrationReport, AromaticError> {
+    let mut report = MigrationReport::default();
     let mut migrations_to_save = HashMap::new();
     migrations_history.iter().for_each(|m| {
         migrations_to_save.insert(&m.name, m);
     });
+    // per namespace, a crate added to the workspace starts its versions over
+    let mut latest_applied: HashMap<Option<String>, (usize, String)> = HashMap::new();
+    for (position, file) in migrations_files.iter().enumerate() {
+        if migrations_to_save.get(&file.name).is_some_and(|m| m.ran) {
+            latest_applied.insert(file.namespace.clone(), (position, file.name.clone()));
+        }
+    }
 
-    for mut migration_file in migrations_files {
+    for (position, mut migration_file) in migrations_files.into_iter().enumerate() {
         let mut id_to_update = None;
+        let mut ran = migration_file.ran;
         if let Some(migration) = migrations_to_save.get(&migration_file.name) {
-            if skip_migration(
-                migration.ran,
-                &migration.name,
-                get_bool_env("RUN_TEST_MIGRATIONS"),
-            )
-            .await
-            {
-                continue;
-            } else {
-                id_to_update = Some(migration.id);
+            ran = migration.ran;
+            id_to_update = Some(migration.id);
+            if ran {
+                let settings = session.settings;
+                let connection = session.connection().await?;
+                verify_checksum(
+                    backend,
+                    settings,
+                    migration,
+                    &migration_file,
+                    connection,
+                )
+                .await?;
             }
         };
-        make_migration(&mut migration_file, transaction, id_to_update).await;
+        if skip_migration(ran, &migration_file, session.settings).await {
+            if !ran {
+                report.skipped.push(migration_file.name);
+            }
+            continue;
+        }
+        // test migrations sort among the others but only run once they are enabled
+        let test_migration = session.settings.test_marker.matches(&migration_file);
+        match latest_applied.get(&migration_file.namespace) {
+            Some((latest, name)) if position < *latest && !test_migration => {
+                match session.settings.out_of_order {
+                    OutOfOrderPolicy::Error => {
+                        return Err(AromaticError::OutOfOrder {
+                            name: migration_file.name,
+                            latest: name.clone(),
+                        })
+                    },
+                    OutOfOrderPolicy::Warn => warn!(
+                        function = "run_migrations",
+                        message = format!(
+                            "Applying {} out of order, {name} is already applied",
+                            migration_file.name
+                        ),
+                    ),
+                    OutOfOrderPolicy::Skip => {
+                        report.skipped.push(migration_file.name);
+                        continue;
+                    },
+                }
+            },
+            _ => (),
+        }
+        if shutdown_requested(shutdown, &migration_file) {
+            report.interrupted_before = Some(migration_file.name);
+            return Ok(report);
+        }
+        let (sql, duration) =
+            make_migration(backend, &mut migration_file, session, id_to_update).await?;
+        report.applied(migration_file.name, sql, duration, session.settings.dry_run);
     }
+    Ok(report)
 }
 
-async fn run_inital_migrations<'a>(
+async fn run_inital_migrations(
+    backend: Backend,
     migrations_files: Vec<MigrationFile>,
-    transaction: &mut Transaction<'a, Sqlite>,
-) {
+    session: &mut Session<'_>,
+    shutdown: &AtomicBool,
+) -> Result<MigrationReport, AromaticError> {
+    let mut report = MigrationReport::default();
     for mut migration_file in migrations_files {
-        if skip_migration(
-            migration_file.ran,
-            &migration_file.name,
-            get_bool_env("RUN_TEST_MIGRATIONS"),
-        )
-        .await
-        {
+        if skip_migration(migration_file.ran, &migration_file, session.settings).await {
+            report.skipped.push(migration_file.name);
             continue;
         }
-        make_migration(&mut migration_file, transaction, None).await;
+        if shutdown_requested(shutdown, &migration_file) {
+            report.interrupted_before = Some(migration_file.name);
+            return Ok(report);
+        }
+        let (sql, duration) =
+            make_migration(backend, &mut migration_file, session, None).await?;
+        report.applied(migration_file.name, sql, duration, session.settings.dry_run);
+    }
+    Ok(report)
+}
+
+fn shutdown_requested(shutdown: &AtomicBool, migration_file: &MigrationFile) -> bool {
+    if !shutdown.load(Ordering::SeqCst) {
+        return false;
+    }
+    warn!(
+        function = "shutdown_requested",
+        message = format!(
+            "Shutdown requested, stopping before {}",
+            migration_file.name
+        ),
+    );
+    true
+}
+
+/// Fails when an applied migration file no longer matches the checksum recorded
+/// for it. Migrations applied before checksums were recorded get theirs stored, and
+/// with `REPAIR_MIGRATION_CHECKSUMS` set a mismatching one is replaced.
+async fn verify_checksum(
+    backend: Backend,
+    settings: &Settings,
+    migration: &Migration,
+    migration_file: &MigrationFile,
+    connection: &mut AnyConnection,
+) -> Result<(), AromaticError> {
+    let found = read_migration(migration_file).await?.checksum;
+    match &migration.checksum {
+        Some(recorded) if *recorded == found => Ok(()),
+        Some(recorded) if !settings.repair_checksums => {
+            Err(AromaticError::ChecksumMismatch {
+                name: migration.name.clone(),
+                recorded: recorded.clone(),
+                found,
+            })
+        },
+        _ => {
+            update_checksum(backend, &settings.table, connection, migration.id, &found)
+                .await
+                .map_err(AromaticError::History)?;
+            Ok(())
+        },
     }
 }
 
 async fn skip_migration(
     migration_has_been_run: bool,
-    name: &str,
-    run_test_migrations: bool,
+    migration_file: &MigrationFile,
+    settings: &Settings,
 ) -> bool {
     if migration_has_been_run {
         // if the migration has been ran we skip it
         true
-    } else if run_test_migrations {
+    } else if settings.run_test_migrations {
         // if the migration hasn't been ran and we want to run the tests migrations, we don't want to skip this migration
         false
     } else {
-        // if we don't want to run the tests migrations we'll check if it contains "test" in the name
-        // if it contains "test" we skip the migration
-        name.contains("test")
+        // if we don't want to run the tests migrations we skip the ones matching the
+        // test marker
+        settings.test_marker.matches(migration_file)
     }
 }
 
-async fn make_migration<'a>(
+async fn make_migration(
+    backend: Backend,
     migration_file: &mut MigrationFile,
-    transaction: &mut Transaction<'a, Sqlite>,
-    id_to_update: Option<u32>,
+    session: &mut Session<'_>,
+    id_to_update: Option<i64>,
+) -> Result<(MigrationSql, Duration), AromaticError> {
+    let sql = read_migration(migration_file).await?;
+    if sql.no_transaction && session.settings.dry_run {
+        warn!(
+            function = "make_migration",
+            message = format!(
+                "{} runs outside a transaction, it is not executed in a dry run",
+                migration_file.name
+            ),
+        );
+        return Ok((sql, Duration::ZERO));
+    }
+    let event = MigrationEvent {
+        name: migration_file.name.clone(),
+        sql: sql.up.clone(),
+    };
+    let hooks = &session.settings.hooks;
+    hooks.before_migration(&event).await;
+    let started = Instant::now();
+    let result = run_migration(
+        backend,
+        migration_file,
+        &sql,
+        session,
+        id_to_update,
+        started,
+    )
+    .await;
+    let duration = started.elapsed();
+    let outcome = match &result {
+        Ok(()) => Ok(duration),
+        Err(err) => Err(err.to_string()),
+    };
+    hooks.after_migration(event, outcome).await;
+    let error = match &result {
+        Err(AromaticError::Migration { source, .. }) => Some(source.to_string()),
+        Err(err @ AromaticError::IntegrityCheck { .. }) => Some(err.to_string()),
+        _ => None,
+    };
+    if let (Some(error), false) = (error, session.settings.dry_run) {
+        record_failure(backend, session, migration_file, id_to_update, &error).await;
+    }
+    result.map(|()| (sql, duration))
+}
+
+/// Rolls back what the failed migration did and records the error in the history,
+/// on a connection of its own so it is kept. Failing to record it is only logged,
+/// the migration error is the one worth returning.
+async fn record_failure(
+    backend: Backend,
+    session: &mut Session<'_>,
+    migration_file: &MigrationFile,
+    id_to_update: Option<i64>,
+    error: &str,
 ) {
-    match execute_migration(&migration_file.path, transaction).await {
+    let table = session.settings.table.quoted(backend);
+    let recorded = async {
+        session.rollback().await?;
+        let (query, arguments) = match id_to_update {
+            Some(id) => Orm::update(&table)
+                .backend(backend)
+                .bind_set(&[("ran", false.into()), ("error", error.into())])
+                .where_()
+                .bind_equal("id", id)
+                .build_arguments(),
+            None => Orm::insert(&table)
+                .backend(backend)
+                .set_columns("error,name,path,ran,namespace")
+                .bind_row(vec![
+                    error.into(),
+                    migration_file.name.as_str().into(),
+                    migration_file.path.display().to_string().into(),
+                    false.into(),
+                    migration_file.namespace.clone().into(),
+                ])
+                .build_arguments(),
+        };
+        let mut connection = session.pool.acquire().await?;
+        sqlx::query_with(&query, arguments)
+            .execute(&mut *connection)
+            .await
+    };
+    if let Err(err) = recorded.await {
+        error!(
+            function = "record_failure",
+            error_message = format!("{err}"),
+            message = format!("Could not record the failure of {}", migration_file.name),
+        );
+    }
+}
+
+async fn run_migration(
+    backend: Backend,
+    migration_file: &mut MigrationFile,
+    sql: &MigrationSql,
+    session: &mut Session<'_>,
+    id_to_update: Option<i64>,
+    started: Instant,
+) -> Result<(), AromaticError> {
+    let result = match (&migration_file.code, sql.no_transaction) {
+        (Some(code), _) => code
+            .up(session.connection().await?)
+            .await
+            .map(|()| 0)
+            .map_err(StatementError::from),
+        (None, true) => session.execute_outside(backend, &sql.up).await,
+        (None, false) => {
+            execute_migration(backend, &sql.up, session.connection().await?).await
+        },
+    };
+    match result {
         Ok(_) => {
+            let settings = session.settings;
+            if settings.integrity_checks == IntegrityChecks::Rollback
+                && backend == Backend::Sqlite
+            {
+                let problems = integrity_problems(session.connection().await?).await?;
+                if !problems.is_empty() {
+                    return Err(AromaticError::IntegrityCheck {
+                        after: migration_file.name.clone(),
+                        problems,
+                    });
+                }
+            }
             migration_file.ran = true;
-            save_or_update(migration_file, transaction, id_to_update).await;
+            let audit = Audit::new(started.elapsed(), settings);
+            let connection = session.connection().await?;
+            save_or_update(
+                backend,
+                &settings.table,
+                migration_file,
+                sql,
+                &audit,
+                connection,
+                id_to_update,
+            )
+            .await?;
+            match sql.no_transaction {
+                true => session.commit().await?,
+                false => session.checkpoint().await?,
+            }
+            Ok(())
         },
-        Err(err) => {
+        Err(StatementError { statement, source }) => {
+            let location = statement.map(|statement| {
+                let offset = backend.error_offset(&source, &sql.up[statement.clone()]);
+                sql.locate(statement, offset)
+            });
             error!(
-                function = "make_migration",
-                error_message = format!("{:?}", err),
-                message = format!("Could not run migration {:?}", migration_file),
+                function = "run_migration",
+                error_message = format!("{source}"),
+                message = format!(
+                    "Could not run migration {}{}",
+                    migration_file.path.display(),
+                    location
+                        .as_ref()
+                        .map(|location| format!(" at {location}"))
+                        .unwrap_or_default()
+                ),
             );
+            Err(AromaticError::Migration {
+                name: migration_file.name.clone(),
+                path: migration_file.path.clone(),
+                location,
+                source,
+            })
         },
     }
 }
 
-async fn save_or_update<'a>(
+/// Who applied a migration, with which version of the application and how long it
+/// took, recorded along with it in the history.
+struct Audit {
+    duration_ms: Option<i64>,
+    applied_by: Option<String>,
+    app_version: Option<String>,
+}
+
+impl Audit {
+    fn new(duration: Duration, settings: &Settings) -> Self {
+        Self {
+            duration_ms: Some(i64::try_from(duration.as_millis()).unwrap_or(i64::MAX)),
+            applied_by: applied_by().clone(),
+            app_version: settings.app_version.clone(),
+        }
+    }
+
+    /// Migrations recorded by [`baseline`] or [`fake`] were never run, they have no
+    /// duration.
+    fn not_run(settings: &Settings) -> Self {
+        Self {
+            duration_ms: None,
+            ..Self::new(Duration::ZERO, settings)
+        }
+    }
+}
+
+/// `user@host` running the migrations, from the environment and `/etc/hostname`.
+/// Looked up once so the runtime isn't blocked reading the file for each migration.
+fn applied_by() -> &'static Option<String> {
+    static APPLIED_BY: OnceLock<Option<String>> = OnceLock::new();
+    APPLIED_BY.get_or_init(lookup_applied_by)
+}
+
+fn lookup_applied_by() -> Option<String> {
+    let var = |names: &[&str]| {
+        names
+            .iter()
+            .find_map(|name| std::env::var(name).ok().filter(|value| !value.is_empty()))
+    };
+    let user = var(&["USER", "USERNAME"]);
+    let host = var(&["HOSTNAME", "COMPUTERNAME"]).or_else(|| {
+        std::fs::read_to_string("/etc/hostname")
+            .ok()
+            .map(|host| host.trim().to_string())
+            .filter(|host| !host.is_empty())
+    });
+    match (user, host) {
+        (Some(user), Some(host)) => Some(format!("{user}@{host}")),
+        (user, host) => user.or(host),
+    }
+}
+
+async fn save_or_update(
+    backend: Backend,
+    table: &HistoryTable,
     migration_file: &mut MigrationFile,
-    transaction: &mut Transaction<'a, Sqlite>,
-    id_to_update: Option<u32>,
-) {
+    sql: &MigrationSql,
+    audit: &Audit,
+    connection: &mut AnyConnection,
+    id_to_update: Option<i64>,
+) -> Result<(), AromaticError> {
     let result = match id_to_update {
-        Some(id) => update_migration_to_history(transaction, id).await,
-        None => save_migration_to_history(migration_file, transaction).await,
+        Some(id) => {
+            update_migration_to_history(backend, table, sql, audit, connection, id).await
+        },
+        None => {
+            save_migration_to_history(
+                backend,
+                table,
+                migration_file,
+                sql,
+                audit,
+                connection,
+            )
+            .await
+        },
     };
     match result {
-        Ok(_) => (),
+        Ok(_) => Ok(()),
         Err(err) => {
             error!(
                 function = "save_or_update",
                 error_message = format!("{err}"),
                 message = format!("Could not save migration {:?}", migration_file),
             );
+            Err(AromaticError::History(err))
         },
     }
 }
 
-async fn execute_migration<'a>(
-    file_path: &PathBuf,
-    transaction: &mut Transaction<'a, Sqlite>,
-) -> Result<u64, MigrationError> {
-    let query = match tokio::fs::read_to_string(file_path).await {
-        Ok(sql) => sql,
-        Err(err) => {
-            error!(
-                function = "execute_migration",
-                error_message = format!("{err}"),
-                message = "error reading files",
-            );
-            return Err(MigrationError::Failed);
-        },
-    };
-    match sqlx::query(&query)
-        .execute(transaction as &mut SqliteConnection)
+async fn read_migration(
+    migration_file: &MigrationFile,
+) -> Result<MigrationSql, AromaticError> {
+    if migration_file.code.is_some() {
+        // stands in for the SQL in the history and reports, and gives it a checksum
+        return Ok(MigrationSql::parse(&format!(
+            "-- rust migration {}",
+            migration_file.name
+        )));
+    }
+    if let Some((up, down)) = migration_file.embedded {
+        let mut sql = MigrationSql::parse(up);
+        if down.is_some() {
+            sql.down = down.map(str::to_string);
+        }
+        return Ok(sql);
+    }
+    let mut sql = MigrationSql::parse(&read_sql_file(&migration_file.path).await?);
+    if let Some(down_path) = &migration_file.down_path {
+        sql.down = Some(read_sql_file(down_path).await?);
+    }
+    Ok(sql)
+}
+
+async fn read_sql_file(file_path: &Path) -> Result<String, AromaticError> {
+    tokio::fs::read_to_string(file_path)
+        .await
+        .map_err(|err| AromaticError::io(file_path, err))
+}
+
+/// A migration that failed, `statement` is the range in the executed SQL of the
+/// statement that did.
+struct StatementError {
+    statement: Option<Range<usize>>,
+    source: sqlx::Error,
+}
+
+impl From<sqlx::Error> for StatementError {
+    fn from(source: sqlx::Error) -> Self {
+        Self {
+            statement: None,
+            source,
+        }
+    }
+}
+
+/// Runs the statements of `query` one after the other so a failure tells which one
+/// it was, failures are logged by the callers along with the migration.
+async fn execute_migration(
+    backend: Backend,
+    query: &str,
+    connection: &mut AnyConnection,
+) -> Result<u64, StatementError> {
+    let mut rows_affected = 0;
+    for statement in split_statements(backend, query) {
+        // executed as raw text, prepared statements can't hold every statement a
+        // migration may need
+        match connection.execute(&query[statement.clone()]).await {
+            Ok(result) => rows_affected += result.rows_affected(),
+            Err(source) => {
+                return Err(StatementError {
+                    statement: Some(statement),
+                    source,
+                })
+            },
+        }
+    }
+    Ok(rows_affected)
+}
+
+async fn update_migration_to_history(
+    backend: Backend,
+    table: &HistoryTable,
+    sql: &MigrationSql,
+    audit: &Audit,
+    connection: &mut AnyConnection,
+    id_to_update: i64,
+) -> Result<u64, sqlx::Error> {
+    let (query, arguments) = Orm::update(&table.quoted(backend))
+        .backend(backend)
+        .bind_set(&[
+            ("ran", true.into()),
+            ("down", sql.down.clone().into()),
+            ("checksum", sql.checksum.as_str().into()),
+            ("duration_ms", audit.duration_ms.into()),
+            ("applied_by", audit.applied_by.clone().into()),
+            ("app_version", audit.app_version.clone().into()),
+            ("error", Value::Null),
+        ])
+        .where_()
+        .bind_equal("id", id_to_update)
+        .build_arguments();
+    match sqlx::query_with(&query, arguments)
+        .execute(&mut *connection)
         .await
     {
         Ok(row) => Ok(row.rows_affected()),
         Err(err) => {
             error!(
-                function = "execute_migration",
+                function = "update_migration_to_history",
                 error_message = format!("{err}"),
-                message = "Error executing th emigration",
+                message = "Error during updating migration into the history",
             );
-            Err(MigrationError::Failed)
+            Err(err)
         },
     }
 }
 
-async fn update_migration_to_history<'a>(
-    transaction: &mut Transaction<'a, Sqlite>,
-    id_to_update: u32,
+async fn update_checksum(
+    backend: Backend,
+    table: &HistoryTable,
+    connection: &mut AnyConnection,
+    id: i64,
+    checksum: &str,
 ) -> Result<u64, sqlx::Error> {
-    let query = Orm::update("migrations")
-        .set("ran = true")
+    let (query, arguments) = Orm::update(&table.quoted(backend))
+        .backend(backend)
+        .bind_set(&[("checksum", checksum.into())])
         .where_()
-        .equal("id", &format!("{}", id_to_update))
-        .ready();
-    match sqlx::query(&query)
-        .execute(transaction as &mut SqliteConnection)
+        .bind_equal("id", id)
+        .build_arguments();
+    let result = sqlx::query_with(&query, arguments)
+        .execute(&mut *connection)
+        .await?;
+    Ok(result.rows_affected())
+}
+
+async fn mark_as_reverted(
+    backend: Backend,
+    table: &HistoryTable,
+    connection: &mut AnyConnection,
+    id: i64,
+) -> Result<u64, sqlx::Error> {
+    let (query, arguments) = Orm::update(&table.quoted(backend))
+        .backend(backend)
+        .bind_set(&[("ran", false.into())])
+        .where_()
+        .bind_equal("id", id)
+        .build_arguments();
+    match sqlx::query_with(&query, arguments)
+        .execute(&mut *connection)
         .await
     {
         Ok(row) => Ok(row.rows_affected()),
         Err(err) => {
             error!(
-                function = "update_migration_to_history",
+                function = "mark_as_reverted",
                 error_message = format!("{err}"),
-                message = "Error during updating migration into the history",
+                message = "Error marking the migration as reverted in the history",
             );
             Err(err)
         },
     }
 }
 
-async fn save_migration_to_history<'a>(
+async fn save_migration_to_history(
+    backend: Backend,
+    table: &HistoryTable,
     migration_file: &MigrationFile,
-    transaction: &mut Transaction<'a, Sqlite>,
+    sql: &MigrationSql,
+    audit: &Audit,
+    connection: &mut AnyConnection,
 ) -> Result<u64, sqlx::Error> {
-    let query = Orm::insert("migrations")
-        .set_columns("name,path,ran")
-        .add_value(&format!(
-            "'{}','{}',{}",
-            migration_file.name,
-            migration_file.path.display(),
-            migration_file.ran
-        ))
-        .ready();
-    match sqlx::query(&query)
-        .execute(transaction as &mut SqliteConnection)
+    let (query, arguments) = Orm::insert(&table.quoted(backend))
+        .backend(backend)
+        .set_columns(
+            "name,path,ran,down,checksum,duration_ms,applied_by,app_version,namespace",
+        )
+        .bind_row(vec![
+            migration_file.name.as_str().into(),
+            migration_file.path.display().to_string().into(),
+            migration_file.ran.into(),
+            sql.down.clone().into(),
+            sql.checksum.as_str().into(),
+            audit.duration_ms.into(),
+            audit.applied_by.clone().into(),
+            audit.app_version.clone().into(),
+            migration_file.namespace.clone().into(),
+        ])
+        .build_arguments();
+    match sqlx::query_with(&query, arguments)
+        .execute(&mut *connection)
         .await
     {
         Ok(row) => Ok(row.rows_affected()),
@@ -375,7 +2380,7 @@ async fn save_migration_to_history<'a>(
 }
 
 async fn commit_transaction(
-    transaction: Transaction<'_, Sqlite>,
+    transaction: Transaction<'_, Any>,
 ) -> Result<(), sqlx::Error> {
     match transaction.commit().await {
         Ok(_) => Ok(()),
@@ -390,8 +2395,24 @@ async fn commit_transaction(
     }
 }
 
-async fn transaction<'a>() -> Result<Transaction<'a, Sqlite>, sqlx::Error> {
-    match connect().await.begin().await {
+async fn rollback_transaction(
+    transaction: Transaction<'_, Any>,
+) -> Result<(), sqlx::Error> {
+    match transaction.rollback().await {
+        Ok(_) => Ok(()),
+        Err(err) => {
+            error!(
+                function = "rollback_transaction",
+                error_message = format!("{err}"),
+                message = "transaction rollback error",
+            );
+            Err(err)
+        },
+    }
+}
+
+async fn transaction<'a>(pool: &AnyPool) -> Result<Transaction<'a, Any>, sqlx::Error> {
+    match pool.begin().await {
         Ok(transaction) => Ok(transaction),
         Err(err) => {
             error!(
@@ -404,43 +2425,407 @@ async fn transaction<'a>() -> Result<Transaction<'a, Sqlite>, sqlx::Error> {
     }
 }
 
-async fn connect() -> SqlitePool {
-    match SqlitePool::connect(&get_env("DATABASE_URL")).await {
-        Ok(db) => db,
+/// Connects to `db_url`, retrying for `DATABASE_CONNECT_TIMEOUT` seconds while the
+/// database is not up yet.
+pub(crate) async fn connect(db_url: &str) -> Result<AnyPool, sqlx::Error> {
+    connect_with_retries(db_url, connect_timeout()).await
+}
+
+pub(crate) async fn connect_with_retries(
+    db_url: &str,
+    timeout: Duration,
+) -> Result<AnyPool, sqlx::Error> {
+    install_default_drivers();
+    match retry_connect(timeout, || AnyPool::connect(db_url)).await {
+        Ok(db) => Ok(db),
         Err(err) => {
             error!(
                 function = "connect",
                 error_message = format!("{err}"),
                 message = "Error connecting to database",
             );
-            panic!()
+            Err(err)
         },
     }
 }
 
+/// How long to keep trying to connect, `DATABASE_CONNECT_TIMEOUT` seconds. It is 0 by
+/// default, failing on the first attempt.
+pub(crate) fn connect_timeout() -> Duration {
+    let seconds = std::env::var("DATABASE_CONNECT_TIMEOUT")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(0);
+    Duration::from_secs(seconds)
+}
+
+/// Runs `attempt` again while it fails because the database can't be reached or is
+/// still starting, waiting twice as long each time, until `timeout` has elapsed. Lets
+/// the migrations run as an init step started before the database is ready.
+pub(crate) async fn retry_connect<T, F, Fut>(
+    timeout: Duration,
+    mut attempt: F,
+) -> Result<T, sqlx::Error>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<T, sqlx::Error>>,
+{
+    const FIRST_DELAY: Duration = Duration::from_millis(100);
+    const MAX_DELAY: Duration = Duration::from_secs(5);
+    let started = Instant::now();
+    let mut delay = FIRST_DELAY;
+    loop {
+        match attempt().await {
+            Err(err) if is_unreachable(&err) && started.elapsed() < timeout => {
+                warn!(
+                    function = "retry_connect",
+                    error_message = format!("{err}"),
+                    message = format!("Database not ready, retrying in {delay:?}"),
+                );
+                tokio::time::sleep(delay.min(timeout.saturating_sub(started.elapsed())))
+                    .await;
+                delay = (delay * 2).min(MAX_DELAY);
+            },
+            result => return result,
+        }
+    }
+}
+
+/// Errors of a database that is down or starting, `57P03` is Postgres refusing
+/// connections until its startup is done.
+fn is_unreachable(err: &sqlx::Error) -> bool {
+    match err {
+        sqlx::Error::Io(_) | sqlx::Error::PoolTimedOut => true,
+        sqlx::Error::Database(err) => err.code().as_deref() == Some("57P03"),
+        _ => false,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn file(path: &str) -> MigrationFile {
+        MigrationFile::from_path(PathBuf::from(path))
+    }
+
     #[tokio::test]
     async fn test_skip_migration_skip_test_migrations() {
-        assert!(!skip_migration(false, "migration", false).await);
+        let settings = Settings {
+            run_test_migrations: false,
+            ..Settings::from_env()
+        };
+        assert!(!skip_migration(false, &file("migration"), &settings).await);
 
-        assert!(skip_migration(true, "migration", false).await);
+        assert!(skip_migration(true, &file("migration"), &settings).await);
 
-        assert!(skip_migration(false, "test_migration", false).await);
+        assert!(skip_migration(false, &file("test_migration"), &settings).await);
 
-        assert!(skip_migration(true, "test_migration", false).await);
+        assert!(skip_migration(true, &file("test_migration"), &settings).await);
     }
 
     #[tokio::test]
     async fn test_skip_migration_run_test_migrations() {
-        assert!(!skip_migration(false, "migration", true).await);
+        let settings = Settings {
+            run_test_migrations: true,
+            ..Settings::from_env()
+        };
+        assert!(!skip_migration(false, &file("migration"), &settings).await);
+
+        assert!(skip_migration(true, &file("migration"), &settings).await);
+
+        assert!(!skip_migration(false, &file("test_migration"), &settings).await);
+
+        assert!(skip_migration(true, &file("test_migration"), &settings).await);
+    }
+
+    #[test]
+    fn test_test_marker() {
+        let suffix = TestMarker::Suffix(".test.sql".to_string());
+        assert!(suffix.matches(&file("migrations/0002_fixtures.test.sql")));
+        assert!(!suffix.matches(&file("migrations/0002_add_latest_column.sql")));
+
+        let folder = TestMarker::Folder("test".to_string());
+        assert!(folder.matches(&file("migrations/test/0002_fixtures.sql")));
+        assert!(!folder.matches(&file("migrations/0002_test_fixtures.sql")));
+
+        assert!(TestMarker::default().matches(&file("migrations/0002_latest.sql")));
+    }
+
+    #[test]
+    fn test_parse_migration_sql_down_section() {
+        let sql =
+            MigrationSql::parse("CREATE TABLE a (id INTEGER);\n-- down\nDROP TABLE a;");
+        assert_eq!(sql.up, "CREATE TABLE a (id INTEGER);");
+        assert_eq!(sql.down.as_deref(), Some("DROP TABLE a;"));
+
+        let sql = MigrationSql::parse("CREATE TABLE a (id INTEGER);");
+        assert_eq!(sql.up, "CREATE TABLE a (id INTEGER);");
+        assert_eq!(sql.down, None);
+    }
+
+    #[test]
+    fn test_parse_migration_sql_no_transaction() {
+        let sql = MigrationSql::parse(
+            "-- no-transaction\nCREATE INDEX CONCURRENTLY a_id ON a (id);",
+        );
+        assert!(sql.no_transaction);
+        assert_eq!(sql.up, "CREATE INDEX CONCURRENTLY a_id ON a (id);");
+        assert!(!MigrationSql::parse("CREATE TABLE a (id INTEGER);").no_transaction);
+    }
+
+    #[test]
+    fn test_migration_sql_locate() {
+        let sql = MigrationSql::parse(
+            "-- no-transaction\nCREATE TABLE a (id INTEGER);\n\nINSERT INTO a\n  \
+             VALUES (1;\nDROP TABLE b;",
+        );
+        let statements = split_statements(Backend::Sqlite, &sql.up);
+        assert_eq!(
+            sql.locate(statements[1].clone(), None),
+            SqlLocation {
+                line: 4,
+                statement: "INSERT INTO a\n  VALUES (1;".to_string(),
+            }
+        );
+        assert_eq!(sql.locate(statements[1].clone(), Some(20)).line, 5);
+        assert_eq!(sql.locate(statements[0].clone(), None).line, 2);
+    }
+
+    #[test]
+    fn test_migration_sql_checksum() {
+        assert_eq!(
+            MigrationSql::parse("").checksum,
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_ne!(
+            MigrationSql::parse("CREATE TABLE a (id INTEGER);").checksum,
+            MigrationSql::parse("CREATE TABLE a (id INTEGER); ").checksum
+        );
+    }
+
+    #[test]
+    fn test_compare_with_history() {
+        let files = ["1_a.sql", "2_b.sql", "3_c.sql"]
+            .into_iter()
+            .map(|name| MigrationFile::from_path(PathBuf::from(name)))
+            .collect();
+        let migration = |id, name: &str, ran| Migration {
+            id,
+            name: name.to_string(),
+            path: name.to_string(),
+            ran,
+            timestamp: "2023-08-01 12:00:00".to_string(),
+            down: None,
+            checksum: None,
+        };
+        let history = vec![
+            migration(1, "1_a.sql", true),
+            migration(2, "0_gone.sql", true),
+            migration(3, "2_b.sql", false),
+        ];
+        let states: Vec<(String, MigrationState)> = compare_with_history(files, history)
+            .into_iter()
+            .map(|s| (s.name, s.state))
+            .collect();
+        assert_eq!(
+            states,
+            [
+                ("1_a.sql".to_string(), MigrationState::Applied),
+                ("2_b.sql".to_string(), MigrationState::Pending),
+                ("3_c.sql".to_string(), MigrationState::Pending),
+                ("0_gone.sql".to_string(), MigrationState::MissingOnDisk),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_embedded_migrations() {
+        const MIGRATIONS: &[EmbeddedMigration] = &[
+            EmbeddedMigration {
+                name: "2_b.down.sql",
+                sql: "DROP TABLE b;",
+            },
+            EmbeddedMigration {
+                name: "2_b.up.sql",
+                sql: "CREATE TABLE b (id INTEGER);",
+            },
+            EmbeddedMigration {
+                name: "1_a.sql",
+                sql: "CREATE TABLE a (id INTEGER);",
+            },
+        ];
+        let files =
+            get_migrations_files(Source::Embedded(MIGRATIONS), &Settings::from_env())
+                .await
+                .unwrap();
+        let names: Vec<&str> = files.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, ["1_a.sql", "2_b.up.sql"]);
+        let sql = read_migration(&files[1]).await.unwrap();
+        assert_eq!(sql.up, "CREATE TABLE b (id INTEGER);");
+        assert_eq!(sql.down.as_deref(), Some("DROP TABLE b;"));
+    }
+
+    #[tokio::test]
+    async fn test_read_migrations_files() {
+        let root = std::env::temp_dir().join("aromatic_test_read_migrations_files");
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("billing/0002_plans.sql")).unwrap();
+        for file in [
+            "0001_users.up.sql",
+            "0001_users.down.sql",
+            ".0001_users.up.sql.swp",
+            ".gitkeep",
+            "README.md",
+            "billing/0003_invoices.sql",
+        ] {
+            std::fs::write(root.join(file), "").unwrap();
+        }
+
+        let folder = root.display().to_string();
+        let mut settings = Settings::from_env();
+        let names = |files: Vec<MigrationFile>| -> Vec<String> {
+            let mut names: Vec<String> = files.into_iter().map(|f| f.name).collect();
+            names.sort();
+            names
+        };
+        let files = read_migrations_files(Source::Folder(&folder), &settings)
+            .await
+            .unwrap();
+        assert!(files[0].down_path.is_some());
+        assert_eq!(names(files), ["0001_users.up.sql"]);
+        settings.recursive = true;
+        settings.extensions.push("md".to_string());
+        let files = read_migrations_files(Source::Folder(&folder), &settings)
+            .await
+            .unwrap();
+        assert_eq!(
+            names(files),
+            ["0001_users.up.sql", "0003_invoices.sql", "README.md"]
+        );
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(&root, root.join("billing/loop")).unwrap();
+            std::os::unix::fs::symlink(
+                root.join("billing/0003_invoices.sql"),
+                root.join("0004_linked.sql"),
+            )
+            .unwrap();
+            let files = read_migrations_files(Source::Folder(&folder), &settings)
+                .await
+                .unwrap();
+            assert_eq!(
+                names(files),
+                [
+                    "0001_users.up.sql",
+                    "0003_invoices.sql",
+                    "0004_linked.sql",
+                    "README.md"
+                ]
+            );
+        }
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_retry_connect() {
+        let mut attempts = 0;
+        let result = retry_connect(Duration::from_secs(5), || {
+            attempts += 1;
+            let result = match attempts {
+                1 | 2 => Err(sqlx::Error::PoolTimedOut),
+                _ => Ok(attempts),
+            };
+            async move { result }
+        })
+        .await;
+        assert_eq!(result.unwrap(), 3);
+
+        let mut attempts = 0;
+        let result: Result<(), _> = retry_connect(Duration::from_secs(5), || {
+            attempts += 1;
+            async { Err(sqlx::Error::RowNotFound) }
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn test_history_table() {
+        let table = HistoryTable::parse("migrations");
+        assert_eq!(table.quoted(Backend::Sqlite), "\"migrations\"");
+        let table = HistoryTable::parse("billing.schema_history");
+        assert_eq!(table.schema.as_deref(), Some("billing"));
+        assert_eq!(
+            table.quoted(Backend::Sqlite),
+            "\"billing\".\"schema_history\""
+        );
+    }
 
-        assert!(skip_migration(true, "migration", true).await);
+    #[test]
+    fn test_sort_migrations_files_by_version() {
+        let files = ["10_c.sql", "2_b.sql", "20230801120000_d.sql", "1_a.sql"]
+            .into_iter()
+            .map(|name| MigrationFile::from_path(PathBuf::from(name)))
+            .collect();
+        let names: Vec<String> = sort_migrations_files(files, false)
+            .unwrap()
+            .into_iter()
+            .map(|f| f.name)
+            .collect();
+        assert_eq!(
+            names,
+            ["1_a.sql", "2_b.sql", "10_c.sql", "20230801120000_d.sql"]
+        );
+    }
 
-        assert!(!skip_migration(false, "test_migration", true).await);
+    #[test]
+    fn test_sort_migrations_files_same_timestamp() {
+        let files = [
+            "20230801120000-10_c.sql",
+            "20230801120000-2_b.sql",
+            "20230801120000_a.sql",
+            "20230801120001_d.sql",
+        ]
+        .into_iter()
+        .map(|name| MigrationFile::from_path(PathBuf::from(name)))
+        .collect();
+        let names: Vec<String> = sort_migrations_files(files, false)
+            .unwrap()
+            .into_iter()
+            .map(|f| f.name)
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "20230801120000_a.sql",
+                "20230801120000-2_b.sql",
+                "20230801120000-10_c.sql",
+                "20230801120001_d.sql"
+            ]
+        );
+    }
 
-        assert!(skip_migration(true, "test_migration", true).await);
+    #[test]
+    fn test_sort_migrations_files_unversioned() {
+        let files = || {
+            ["b.sql", "2_b.sql", "a.sql"]
+                .into_iter()
+                .map(|name| MigrationFile::from_path(PathBuf::from(name)))
+                .collect()
+        };
+        assert!(matches!(
+            sort_migrations_files(files(), false),
+            Err(AromaticError::UnversionedMigration(name)) if name == "b.sql"
+        ));
+        let names: Vec<String> = sort_migrations_files(files(), true)
+            .unwrap()
+            .into_iter()
+            .map(|f| f.name)
+            .collect();
+        assert_eq!(names, ["2_b.sql", "a.sql", "b.sql"]);
     }
 }
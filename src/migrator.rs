@@ -0,0 +1,789 @@
+use std::{
+    future::Future,
+    sync::{atomic::AtomicBool, Arc},
+    time::Duration,
+};
+
+use sqlx::AnyPool;
+
+use super::{
+    code_migration::Migration,
+    hooks::Hooks,
+    migrations::{
+        connect_with_retries, create_database, migration_database_url, run_baseline,
+        run_fake, run_history, run_migrate, run_migrate_to, run_redo, run_rollback,
+        run_status, run_validate, HistoryTable, Settings, Source,
+    },
+    AromaticError, Backend, EmbeddedMigration, HistoryRecord, IntegrityChecks,
+    MigrationEvent, MigrationReport, MigrationStatus, OpenTransactionsPolicy,
+    OutOfOrderPolicy, SqlitePragmas, TestMarker,
+};
+
+const DEFAULT_FOLDER: &str = "migrations";
+
+#[derive(Debug, Clone)]
+pub(crate) enum MigrationsSource {
+    Folder(String),
+    Embedded(&'static [EmbeddedMigration]),
+}
+
+impl MigrationsSource {
+    pub(crate) fn as_source(&self) -> Source<'_> {
+        match self {
+            Self::Folder(folder_path) => Source::Folder(folder_path),
+            Self::Embedded(migrations) => Source::Embedded(migrations),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Database {
+    Env,
+    Url(String),
+    Pool(AnyPool),
+}
+
+/// Runs the migrations of a folder, or embedded in the binary, against a database.
+/// Built with [`Migrator::builder`], the options left unset are read from the same
+/// environment variables as [`migrate`](crate::migrate).
+///
+/// ```ignore
+/// let report = Migrator::builder()
+///     .folder("migrations")
+///     .database_url("sqlite://app.db")
+///     .table("billing.migrations")
+///     .build()
+///     .migrate()
+///     .await?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct Migrator {
+    source: MigrationsSource,
+    database: Database,
+    settings: Settings,
+}
+
+impl Migrator {
+    pub fn builder() -> MigratorBuilder {
+        MigratorBuilder::default()
+    }
+
+    /// Applies the pending migrations, see [`migrate`](crate::migrate).
+    pub async fn migrate(&self) -> Result<MigrationReport, AromaticError> {
+        self.migrate_with_shutdown(&AtomicBool::new(false)).await
+    }
+
+    /// See [`migrate_with_shutdown`](crate::migrate_with_shutdown).
+    pub async fn migrate_with_shutdown(
+        &self,
+        shutdown: &AtomicBool,
+    ) -> Result<MigrationReport, AromaticError> {
+        let report = async {
+            let (backend, pool) = self.connect(true).await?;
+            run_migrate(backend, &pool, self.source(), &self.settings, shutdown).await
+        };
+        self.complete(report.await).await
+    }
+
+    /// See [`migrate_to`](crate::migrate_to).
+    pub async fn migrate_to(
+        &self,
+        target: &str,
+    ) -> Result<MigrationReport, AromaticError> {
+        let report = async {
+            let (backend, pool) = self.connect(true).await?;
+            run_migrate_to(backend, &pool, self.source(), &self.settings, target).await
+        };
+        self.complete(report.await).await
+    }
+
+    /// See [`baseline`](crate::baseline).
+    pub async fn baseline(&self, up_to: &str) -> Result<Vec<String>, AromaticError> {
+        let (backend, pool) = self.connect(true).await?;
+        run_baseline(backend, &pool, self.source(), &self.settings, up_to).await
+    }
+
+    /// See [`fake`](crate::fake).
+    pub async fn fake(&self, names: &[&str]) -> Result<Vec<String>, AromaticError> {
+        let (backend, pool) = self.connect(true).await?;
+        run_fake(backend, &pool, self.source(), &self.settings, names).await
+    }
+
+    /// See [`rollback`](crate::rollback).
+    pub async fn rollback(&self, steps: u32) -> Result<Vec<String>, AromaticError> {
+        let (backend, pool) = self.connect(false).await?;
+        run_rollback(backend, &pool, self.source(), &self.settings, steps).await
+    }
+
+    /// See [`redo`](crate::redo).
+    pub async fn redo(&self, steps: u32) -> Result<MigrationReport, AromaticError> {
+        let report = async {
+            let (backend, pool) = self.connect(false).await?;
+            run_redo(backend, &pool, self.source(), &self.settings, steps).await
+        };
+        self.complete(report.await).await
+    }
+
+    /// See [`migration_history`](crate::migration_history).
+    pub async fn history(&self) -> Result<Vec<HistoryRecord>, AromaticError> {
+        let (backend, pool) = self.connect(false).await?;
+        run_history(backend, &pool, &self.settings).await
+    }
+
+    /// See [`migration_status`](crate::migration_status).
+    pub async fn status(&self) -> Result<Vec<MigrationStatus>, AromaticError> {
+        let (backend, pool) = self.connect(false).await?;
+        run_status(backend, &pool, self.source(), &self.settings).await
+    }
+
+    /// See [`validate`](crate::validate).
+    pub async fn validate(&self) -> Result<Vec<String>, AromaticError> {
+        let (backend, pool) = self.connect(false).await?;
+        run_validate(backend, &pool, self.source(), &self.settings).await
+    }
+
+    /// Hands the outcome of a run to the `on_complete` hook.
+    async fn complete(
+        &self,
+        report: Result<MigrationReport, AromaticError>,
+    ) -> Result<MigrationReport, AromaticError> {
+        let outcome = match &report {
+            Ok(report) => Ok(report.clone()),
+            Err(err) => Err(err.to_string()),
+        };
+        self.settings.hooks.complete(outcome).await;
+        report
+    }
+
+    fn source(&self) -> Source<'_> {
+        self.source.as_source()
+    }
+
+    /// The backend and a pool to run on, creating the database first if `create` is
+    /// set and it was given by url.
+    async fn connect(&self, create: bool) -> Result<(Backend, AnyPool), AromaticError> {
+        let (db_url, backend) = match &self.database {
+            Database::Pool(pool) => {
+                let db_url = pool.connect_options().database_url.to_string();
+                let backend = Backend::from_url(&db_url).ok_or_else(|| {
+                    AromaticError::Config(
+                        "the pool does not use a supported database".to_string(),
+                    )
+                })?;
+                return Ok((backend, pool.clone()));
+            },
+            Database::Url(db_url) => {
+                let backend = Backend::from_url(db_url).ok_or_else(|| {
+                    AromaticError::Config(format!(
+                        "{db_url} does not point to a supported database"
+                    ))
+                })?;
+                (db_url.clone(), backend)
+            },
+            Database::Env => migration_database_url()?,
+        };
+        if create {
+            create_database(&db_url).await;
+        }
+        let pragmas = &self.settings.sqlite_pragmas;
+        let timeout = self.settings.connect_timeout;
+        let pool = match backend == Backend::Sqlite && !pragmas.is_empty() {
+            true => pragmas.connect(&db_url, timeout).await?,
+            false => connect_with_retries(&db_url, timeout).await?,
+        };
+        Ok((backend, pool))
+    }
+}
+
+/// Options of a [`Migrator`]. Without a source the `migrations` folder is used and
+/// without a database `MIGRATION_DATABASE_URL`, or `DATABASE_URL` if it isn't set.
+#[derive(Debug, Default)]
+pub struct MigratorBuilder {
+    source: Option<MigrationsSource>,
+    database: Option<Database>,
+    dry_run: Option<bool>,
+    backup: Option<bool>,
+    single_transaction: Option<bool>,
+    run_test_migrations: Option<bool>,
+    test_marker: Option<TestMarker>,
+    allow_unversioned: Option<bool>,
+    extensions: Option<Vec<String>>,
+    recursive: Option<bool>,
+    out_of_order: Option<OutOfOrderPolicy>,
+    integrity_checks: Option<IntegrityChecks>,
+    repair_checksums: Option<bool>,
+    retries: Option<u32>,
+    lock_timeout: Option<Duration>,
+    connect_timeout: Option<Duration>,
+    open_transactions: Option<OpenTransactionsPolicy>,
+    sqlite_pragmas: Option<SqlitePragmas>,
+    table: Option<String>,
+    app_version: Option<String>,
+    hooks: Hooks,
+    code_migrations: Vec<Arc<dyn Migration>>,
+    namespaces: Vec<(String, MigrationsSource)>,
+}
+
+impl MigratorBuilder {
+    pub fn folder(mut self, folder_path: &str) -> Self {
+        self.source = Some(MigrationsSource::Folder(folder_path.to_string()));
+        self
+    }
+
+    /// Migrations compiled in with [`embed_migrations!`](crate::embed_migrations).
+    pub fn embedded(mut self, migrations: &'static [EmbeddedMigration]) -> Self {
+        self.source = Some(MigrationsSource::Embedded(migrations));
+        self
+    }
+
+    /// Adds the folder of another crate of the workspace, its migrations are named
+    /// `namespace/file` and run merged with the others in version order.
+    pub fn namespaced_folder(mut self, namespace: &str, folder_path: &str) -> Self {
+        self.namespaces.push((
+            namespace.to_string(),
+            MigrationsSource::Folder(folder_path.to_string()),
+        ));
+        self
+    }
+
+    /// Same as [`namespaced_folder`](Self::namespaced_folder) for embedded migrations.
+    pub fn namespaced_embedded(
+        mut self,
+        namespace: &str,
+        migrations: &'static [EmbeddedMigration],
+    ) -> Self {
+        self.namespaces.push((
+            namespace.to_string(),
+            MigrationsSource::Embedded(migrations),
+        ));
+        self
+    }
+
+    pub fn database_url(mut self, db_url: &str) -> Self {
+        self.database = Some(Database::Url(db_url.to_string()));
+        self
+    }
+
+    /// Runs on a pool the application already manages, the database is not created.
+    pub fn pool(mut self, pool: AnyPool) -> Self {
+        self.database = Some(Database::Pool(pool));
+        self
+    }
+
+    /// Rolls everything back at the end, `DRY_RUN_MIGRATIONS`.
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = Some(dry_run);
+        self
+    }
+
+    /// Copies a SQLite database before applying anything, `BACKUP_BEFORE_MIGRATIONS`.
+    pub fn backup(mut self, backup: bool) -> Self {
+        self.backup = Some(backup);
+        self
+    }
+
+    /// One transaction for the whole run instead of one per file,
+    /// `SINGLE_TRANSACTION_MIGRATIONS`.
+    pub fn single_transaction(mut self, single_transaction: bool) -> Self {
+        self.single_transaction = Some(single_transaction);
+        self
+    }
+
+    /// Also runs the migrations matching the test marker, `RUN_TEST_MIGRATIONS`.
+    pub fn run_test_migrations(mut self, run_test_migrations: bool) -> Self {
+        self.run_test_migrations = Some(run_test_migrations);
+        self
+    }
+
+    /// How test migrations are recognized, by default the ones with `test` in their
+    /// name.
+    pub fn test_marker(mut self, test_marker: TestMarker) -> Self {
+        self.test_marker = Some(test_marker);
+        self
+    }
+
+    /// `ALLOW_UNVERSIONED_MIGRATIONS`.
+    pub fn allow_unversioned(mut self, allow_unversioned: bool) -> Self {
+        self.allow_unversioned = Some(allow_unversioned);
+        self
+    }
+
+    /// Extensions of the files of the folder that are migrations, `MIGRATION_EXTENSIONS`.
+    pub fn extensions(mut self, extensions: &[&str]) -> Self {
+        self.extensions = Some(extensions.iter().map(|e| e.to_string()).collect());
+        self
+    }
+
+    /// Also reads the subfolders of the folder, `RECURSIVE_MIGRATIONS`.
+    pub fn recursive(mut self, recursive: bool) -> Self {
+        self.recursive = Some(recursive);
+        self
+    }
+
+    /// What to do with pending migrations sorting before the latest applied one,
+    /// `OUT_OF_ORDER_MIGRATIONS`.
+    pub fn out_of_order(mut self, policy: OutOfOrderPolicy) -> Self {
+        self.out_of_order = Some(policy);
+        self
+    }
+
+    /// Checks a SQLite database for corruption and broken references after migrating,
+    /// `MIGRATION_INTEGRITY_CHECKS`.
+    pub fn integrity_checks(mut self, checks: IntegrityChecks) -> Self {
+        self.integrity_checks = Some(checks);
+        self
+    }
+
+    /// `REPAIR_MIGRATION_CHECKSUMS`.
+    pub fn repair_checksums(mut self, repair_checksums: bool) -> Self {
+        self.repair_checksums = Some(repair_checksums);
+        self
+    }
+
+    /// `MIGRATION_RETRIES`.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = Some(retries);
+        self
+    }
+
+    /// `MIGRATION_LOCK_TIMEOUT`.
+    pub fn lock_timeout(mut self, lock_timeout: Duration) -> Self {
+        self.lock_timeout = Some(lock_timeout);
+        self
+    }
+
+    /// How long to keep retrying while the database is not ready,
+    /// `DATABASE_CONNECT_TIMEOUT`.
+    pub fn connect_timeout(mut self, connect_timeout: Duration) -> Self {
+        self.connect_timeout = Some(connect_timeout);
+        self
+    }
+
+    /// What to do when other sessions hold transactions open before running,
+    /// `MIGRATION_OPEN_TRANSACTIONS`.
+    pub fn open_transactions(mut self, policy: OpenTransactionsPolicy) -> Self {
+        self.open_transactions = Some(policy);
+        self
+    }
+
+    /// Pragmas of the SQLite connections opened for the run, `SQLITE_PRAGMAS`. A pool
+    /// given with [`pool`](Self::pool) is used as it is.
+    pub fn sqlite_pragmas(mut self, pragmas: SqlitePragmas) -> Self {
+        self.sqlite_pragmas = Some(pragmas);
+        self
+    }
+
+    /// History table, `TABLE` or `SCHEMA.TABLE`, `MIGRATIONS_TABLE`.
+    pub fn table(mut self, table: &str) -> Self {
+        self.table = Some(table.to_string());
+        self
+    }
+
+    /// Version of the application recorded with each migration it applies,
+    /// `MIGRATION_APP_VERSION`.
+    pub fn app_version(mut self, app_version: &str) -> Self {
+        self.app_version = Some(app_version.to_string());
+        self
+    }
+
+    /// Called before each migration runs.
+    pub fn on_before_migration<F, Fut>(mut self, hook: F) -> Self
+    where
+        F: Fn(MigrationEvent) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.hooks.before_migration = Some(Arc::new(move |event| Box::pin(hook(event))));
+        self
+    }
+
+    /// Called after each migration with how long it took, or why it failed.
+    pub fn on_after_migration<F, Fut>(mut self, hook: F) -> Self
+    where
+        F: Fn(MigrationEvent, Result<Duration, String>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.hooks.after_migration =
+            Some(Arc::new(move |event, result| Box::pin(hook(event, result))));
+        self
+    }
+
+    /// Called once a run that applies migrations, `migrate`, `migrate_to` or `redo`,
+    /// is over with its report or why it failed.
+    pub fn on_complete<F, Fut>(mut self, hook: F) -> Self
+    where
+        F: Fn(Result<MigrationReport, String>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.hooks.complete = Some(Arc::new(move |report| Box::pin(hook(report))));
+        self
+    }
+
+    /// Adds a migration written in Rust, it runs in order with the files.
+    pub fn migration(mut self, migration: impl Migration + 'static) -> Self {
+        self.code_migrations.push(Arc::new(migration));
+        self
+    }
+
+    pub fn build(self) -> Migrator {
+        let env = Settings::from_env();
+        Migrator {
+            source: self
+                .source
+                .unwrap_or_else(|| MigrationsSource::Folder(DEFAULT_FOLDER.to_string())),
+            database: self.database.unwrap_or(Database::Env),
+            settings: Settings {
+                dry_run: self.dry_run.unwrap_or(env.dry_run),
+                backup: self.backup.unwrap_or(env.backup),
+                single_transaction: self
+                    .single_transaction
+                    .unwrap_or(env.single_transaction),
+                run_test_migrations: self
+                    .run_test_migrations
+                    .unwrap_or(env.run_test_migrations),
+                test_marker: self.test_marker.unwrap_or(env.test_marker),
+                allow_unversioned: self
+                    .allow_unversioned
+                    .unwrap_or(env.allow_unversioned),
+                extensions: self.extensions.unwrap_or(env.extensions),
+                recursive: self.recursive.unwrap_or(env.recursive),
+                out_of_order: self.out_of_order.unwrap_or(env.out_of_order),
+                integrity_checks: self.integrity_checks.unwrap_or(env.integrity_checks),
+                repair_checksums: self.repair_checksums.unwrap_or(env.repair_checksums),
+                retries: self.retries.unwrap_or(env.retries),
+                lock_timeout: self.lock_timeout.unwrap_or(env.lock_timeout),
+                connect_timeout: self.connect_timeout.unwrap_or(env.connect_timeout),
+                open_transactions: self
+                    .open_transactions
+                    .unwrap_or(env.open_transactions),
+                sqlite_pragmas: self.sqlite_pragmas.unwrap_or(env.sqlite_pragmas),
+                table: self
+                    .table
+                    .map_or(env.table, |table| HistoryTable::parse(&table)),
+                app_version: self.app_version.or(env.app_version),
+                hooks: self.hooks,
+                code_migrations: self.code_migrations,
+                namespaces: self.namespaces,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::TestDb;
+
+    #[test]
+    fn test_builder_overrides_env() {
+        let migrator = Migrator::builder()
+            .embedded(&[])
+            .database_url("sqlite::memory:")
+            .dry_run(true)
+            .retries(0)
+            .table("audit.migrations")
+            .build();
+        assert!(matches!(migrator.source(), Source::Embedded([])));
+        assert!(migrator.settings.dry_run);
+        assert_eq!(migrator.settings.retries, 0);
+        assert_eq!(
+            migrator.settings.table,
+            HistoryTable::parse("audit.migrations")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_hooks() {
+        let db = TestDb::new("SELECT 1;").await.unwrap();
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let (before, after, complete) = (calls.clone(), calls.clone(), calls.clone());
+        let report = Migrator::builder()
+            .embedded(&[
+                EmbeddedMigration {
+                    name: "0001_users.sql",
+                    sql: "CREATE TABLE users (id INTEGER);",
+                },
+                EmbeddedMigration {
+                    name: "0002_broken.sql",
+                    sql: "CREATE TABLE users (id INTEGER);",
+                },
+            ])
+            .pool(db.pool().clone())
+            .on_before_migration(move |event| {
+                let calls = before.clone();
+                async move { calls.lock().unwrap().push(format!("before {}", event.name)) }
+            })
+            .on_after_migration(move |event, result| {
+                let calls = after.clone();
+                async move {
+                    let outcome = if result.is_ok() { "ok" } else { "failed" };
+                    calls.lock().unwrap().push(format!("after {} {outcome}", event.name))
+                }
+            })
+            .on_complete(move |report| {
+                let calls = complete.clone();
+                async move { calls.lock().unwrap().push(format!("complete {}", report.is_ok())) }
+            })
+            .retries(0)
+            .build()
+            .migrate()
+            .await;
+        assert!(report.is_err());
+        assert_eq!(
+            *calls.lock().unwrap(),
+            [
+                "before 0001_users.sql",
+                "after 0001_users.sql ok",
+                "before 0002_broken.sql",
+                "after 0002_broken.sql failed",
+                "complete false",
+            ]
+        );
+    }
+
+    struct Backfill;
+
+    impl Migration for Backfill {
+        fn name(&self) -> &str {
+            "0002_backfill"
+        }
+
+        fn up<'a>(
+            &'a self,
+            connection: &'a mut sqlx::AnyConnection,
+        ) -> crate::BoxFuture<'a, sqlx::Result<()>> {
+            Box::pin(async move {
+                sqlx::query("INSERT INTO users (id) VALUES (1), (2);")
+                    .execute(connection)
+                    .await?;
+                Ok(())
+            })
+        }
+
+        fn down<'a>(
+            &'a self,
+            connection: &'a mut sqlx::AnyConnection,
+        ) -> Option<crate::BoxFuture<'a, sqlx::Result<()>>> {
+            Some(Box::pin(async move {
+                sqlx::query("DELETE FROM users;")
+                    .execute(connection)
+                    .await?;
+                Ok(())
+            }))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_code_migrations() {
+        let db = TestDb::new("SELECT 1;").await.unwrap();
+        let migrator = Migrator::builder()
+            .embedded(&[
+                EmbeddedMigration {
+                    name: "0001_users.sql",
+                    sql: "CREATE TABLE users (id INTEGER);",
+                },
+                EmbeddedMigration {
+                    name: "0003_count.sql",
+                    sql: "CREATE TABLE counts AS SELECT COUNT(*) AS n FROM users;\n\
+                          -- down\nDROP TABLE counts;",
+                },
+            ])
+            .migration(Backfill)
+            .pool(db.pool().clone())
+            .build();
+        let report = migrator.migrate().await.unwrap();
+        assert_eq!(
+            report.applied,
+            ["0001_users.sql", "0002_backfill", "0003_count.sql"]
+        );
+        db.assert_rows("SELECT n FROM counts;", &[&["2"]]).await;
+
+        assert_eq!(
+            migrator.rollback(2).await.unwrap(),
+            ["0003_count.sql", "0002_backfill"]
+        );
+        db.assert_rows("SELECT COUNT(*) FROM users;", &[&["0"]])
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_out_of_order() {
+        let db = TestDb::new("SELECT 1;").await.unwrap();
+        let migrator = |migrations, policy| {
+            Migrator::builder()
+                .embedded(migrations)
+                .pool(db.pool().clone())
+                .out_of_order(policy)
+                .build()
+        };
+        const USERS: EmbeddedMigration = EmbeddedMigration {
+            name: "0001_users.sql",
+            sql: "CREATE TABLE users (id INTEGER);",
+        };
+        const TAGS: EmbeddedMigration = EmbeddedMigration {
+            name: "0002_tags.sql",
+            sql: "CREATE TABLE tags (id INTEGER);",
+        };
+        const POSTS: EmbeddedMigration = EmbeddedMigration {
+            name: "0003_posts.sql",
+            sql: "CREATE TABLE posts (id INTEGER);",
+        };
+        let merged = &[USERS, TAGS, POSTS];
+        migrator(&[USERS, POSTS], OutOfOrderPolicy::Error)
+            .migrate()
+            .await
+            .unwrap();
+
+        let err = migrator(merged, OutOfOrderPolicy::Error)
+            .migrate()
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AromaticError::OutOfOrder { .. }), "{err}");
+        let report = migrator(merged, OutOfOrderPolicy::Skip)
+            .migrate()
+            .await
+            .unwrap();
+        assert_eq!(report.skipped, ["0002_tags.sql"]);
+        let report = migrator(merged, OutOfOrderPolicy::Warn)
+            .migrate()
+            .await
+            .unwrap();
+        assert_eq!(report.applied, ["0002_tags.sql"]);
+    }
+
+    #[tokio::test]
+    async fn test_out_of_order_test_migrations() {
+        let db = TestDb::new("SELECT 1;").await.unwrap();
+        let migrator = |run_test_migrations| {
+            Migrator::builder()
+                .embedded(&[
+                    EmbeddedMigration {
+                        name: "0001_users.sql",
+                        sql: "CREATE TABLE users (id INTEGER);",
+                    },
+                    EmbeddedMigration {
+                        name: "0002_test_users.sql",
+                        sql: "INSERT INTO users VALUES (1);",
+                    },
+                    EmbeddedMigration {
+                        name: "0003_posts.sql",
+                        sql: "CREATE TABLE posts (id INTEGER);",
+                    },
+                ])
+                .pool(db.pool().clone())
+                .run_test_migrations(run_test_migrations)
+                .out_of_order(OutOfOrderPolicy::Error)
+                .build()
+        };
+        let report = migrator(false).migrate().await.unwrap();
+        assert_eq!(report.applied, ["0001_users.sql", "0003_posts.sql"]);
+        assert_eq!(report.skipped, ["0002_test_users.sql"]);
+
+        let report = migrator(true).migrate().await.unwrap();
+        assert_eq!(report.applied, ["0002_test_users.sql"]);
+        db.assert_rows("SELECT id FROM users;", &[&["1"]]).await;
+    }
+
+    #[tokio::test]
+    async fn test_integrity_checks_rollback() {
+        let db = TestDb::new(
+            "PRAGMA foreign_keys = OFF;
+             CREATE TABLE users (id INTEGER PRIMARY KEY);
+             CREATE TABLE posts (id INTEGER, user_id INTEGER REFERENCES users (id));",
+        )
+        .await
+        .unwrap();
+        let err = Migrator::builder()
+            .embedded(&[EmbeddedMigration {
+                name: "0001_posts.sql",
+                sql: "INSERT INTO posts VALUES (1, 42);",
+            }])
+            .pool(db.pool().clone())
+            .integrity_checks(IntegrityChecks::Rollback)
+            .build()
+            .migrate()
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "integrity check failed after 0001_posts.sql:\n\
+             posts row 1 references a missing row of users"
+        );
+        db.assert_rows("SELECT COUNT(*) FROM posts;", &[&["0"]])
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_namespaces() {
+        let db = TestDb::new("SELECT 1;").await.unwrap();
+        let report = Migrator::builder()
+            .embedded(&[
+                EmbeddedMigration {
+                    name: "0001_users.sql",
+                    sql: "CREATE TABLE users (id INTEGER);",
+                },
+                EmbeddedMigration {
+                    name: "0003_posts.sql",
+                    sql: "CREATE TABLE posts (id INTEGER);",
+                },
+            ])
+            .namespaced_embedded(
+                "billing",
+                &[EmbeddedMigration {
+                    name: "0002_plans.sql",
+                    sql: "CREATE TABLE plans (id INTEGER);",
+                }],
+            )
+            .pool(db.pool().clone())
+            .build()
+            .migrate()
+            .await
+            .unwrap();
+        assert_eq!(
+            report.applied,
+            ["0001_users.sql", "billing/0002_plans.sql", "0003_posts.sql"]
+        );
+        let namespace: String = sqlx::query_scalar(
+            "SELECT namespace FROM migrations WHERE name = 'billing/0002_plans.sql';",
+        )
+        .fetch_one(db.pool())
+        .await
+        .unwrap();
+        assert_eq!(namespace, "billing");
+
+        // a crate added later starts at 0001 while the others are already past it
+        let report = Migrator::builder()
+            .embedded(&[
+                EmbeddedMigration {
+                    name: "0001_users.sql",
+                    sql: "CREATE TABLE users (id INTEGER);",
+                },
+                EmbeddedMigration {
+                    name: "0003_posts.sql",
+                    sql: "CREATE TABLE posts (id INTEGER);",
+                },
+            ])
+            .namespaced_embedded(
+                "billing",
+                &[EmbeddedMigration {
+                    name: "0002_plans.sql",
+                    sql: "CREATE TABLE plans (id INTEGER);",
+                }],
+            )
+            .namespaced_embedded(
+                "search",
+                &[EmbeddedMigration {
+                    name: "0001_index.sql",
+                    sql: "CREATE TABLE documents (id INTEGER);",
+                }],
+            )
+            .pool(db.pool().clone())
+            .out_of_order(OutOfOrderPolicy::Error)
+            .build()
+            .migrate()
+            .await
+            .unwrap();
+        assert_eq!(report.applied, ["search/0001_index.sql"]);
+    }
+}
@@ -1,5 +1,9 @@
 use std::marker::PhantomData;
 
+use sqlx::{any::AnyArguments, Arguments};
+
+use super::Backend;
+
 pub struct Select;
 pub struct From;
 pub struct Where;
@@ -9,11 +13,82 @@ pub struct Table;
 pub struct Insert;
 pub struct InsertColumns;
 pub struct Set;
+pub struct Values;
+
+/// A value bound to a placeholder of the query by the `bind` methods, instead of
+/// being written into its text.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Text(String),
+}
+
+impl std::convert::From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Self::Bool(value)
+    }
+}
+
+impl std::convert::From<i32> for Value {
+    fn from(value: i32) -> Self {
+        Self::Int(value.into())
+    }
+}
 
+impl std::convert::From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Self::Int(value)
+    }
+}
+
+impl std::convert::From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Self::Float(value)
+    }
+}
+
+impl std::convert::From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Self::Text(value.to_string())
+    }
+}
+
+impl std::convert::From<String> for Value {
+    fn from(value: String) -> Self {
+        Self::Text(value)
+    }
+}
+
+impl<T: Into<Value>> std::convert::From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Self::Null, Into::into)
+    }
+}
+
+impl Value {
+    /// [`Value::Null`] is never bound, see [`Orm::bind`].
+    fn add_to(self, arguments: &mut AnyArguments<'static>) {
+        match self {
+            Self::Null => (),
+            Self::Bool(value) => arguments.add(value),
+            Self::Int(value) => arguments.add(value),
+            Self::Float(value) => arguments.add(value),
+            Self::Text(value) => arguments.add(value),
+        }
+    }
+}
+
+/// Query builder writing the SQL as text. Values given to the `bind` methods go
+/// into placeholders of the [`Orm::backend`] instead, see [`Orm::build`].
 pub struct Orm<State = Select> {
     query: String,
     state: PhantomData<State>,
     has_where_clause: bool,
+    backend: Backend,
+    values: Vec<Value>,
 }
 
 impl Orm {
@@ -22,6 +97,8 @@ impl Orm {
             query: format!("SELECT {}", columns),
             has_where_clause: false,
             state: PhantomData,
+            backend: Backend::Sqlite,
+            values: Vec::new(),
         }
     }
 
@@ -34,6 +111,19 @@ impl Orm {
             query: format!("INSERT OR IGNORE INTO {table}"),
             has_where_clause: false,
             state: PhantomData,
+            backend: Backend::Sqlite,
+            values: Vec::new(),
+        }
+    }
+
+    /// MySQL/MariaDB flavour of [`Orm::insert_or_ignore`].
+    pub fn insert_ignore(table: &str) -> Orm<InsertColumns> {
+        Orm {
+            query: format!("INSERT IGNORE INTO {table}"),
+            has_where_clause: false,
+            state: PhantomData,
+            backend: Backend::Sqlite,
+            values: Vec::new(),
         }
     }
 
@@ -42,6 +132,8 @@ impl Orm {
             query: format!("INSERT INTO {table}"),
             has_where_clause: false,
             state: PhantomData,
+            backend: Backend::Sqlite,
+            values: Vec::new(),
         }
     }
 
@@ -50,6 +142,32 @@ impl Orm {
             query: format!("DELETE {}", columns),
             has_where_clause: false,
             state: PhantomData,
+            backend: Backend::Sqlite,
+            values: Vec::new(),
+        }
+    }
+
+    /// Row constructor `VALUES (..), (..)` starting with `row`, see
+    /// [`Orm::<Values>::as_table`] and [`Orm::<Values>::as_cte`] to select from it.
+    pub fn values(row: &str) -> Orm<Values> {
+        Orm {
+            query: format!("VALUES ({row})"),
+            has_where_clause: false,
+            state: PhantomData,
+            backend: Backend::Sqlite,
+            values: Vec::new(),
+        }
+    }
+
+    /// `WITH ctes SELECT columns`, the common table expressions are given already
+    /// joined, e.g. built with [`Orm::<Values>::as_cte`].
+    pub fn with(ctes: &str, columns: &str) -> Orm<From> {
+        Orm {
+            query: format!("WITH {ctes} SELECT {columns}"),
+            has_where_clause: false,
+            state: PhantomData,
+            backend: Backend::Sqlite,
+            values: Vec::new(),
         }
     }
 
@@ -58,6 +176,8 @@ impl Orm {
             query: format!("UPDATE {}", columns),
             has_where_clause: false,
             state: PhantomData,
+            backend: Backend::Sqlite,
+            values: Vec::new(),
         }
     }
 }
@@ -65,28 +185,82 @@ impl Orm {
 impl Orm<Set> {
     pub fn set(self, values: &str) -> Orm<Where> {
         Orm {
-            query: format!("{} {values}", self.query),
+            query: format!("{} SET {values}", self.query),
             has_where_clause: false,
             state: PhantomData,
+            backend: self.backend,
+            values: self.values,
         }
     }
+
+    /// `SET column = placeholder, ..` with the values bound.
+    pub fn bind_set(mut self, assignments: &[(&str, Value)]) -> Orm<Where> {
+        let assignments = assignments
+            .iter()
+            .map(|(column, value)| format!("{column} = {}", self.bind(value.clone())))
+            .collect::<Vec<String>>()
+            .join(", ");
+        self.set(&assignments)
+    }
 }
 
 #[allow(clippy::single_char_add_str)]
 impl<State> Orm<State> {
+    /// The finished query. Calling it again returns the same text, so a builder
+    /// can be kept around and rendered for caching or snapshots.
     pub fn ready(&mut self) -> String {
         if self.query.ends_with(',') {
             self.query.pop();
         }
-        self.query.push_str(";");
+        if !self.query.ends_with(';') {
+            self.query.push_str(";");
+        }
         self.query.clone()
     }
 
+    /// Database whose placeholders the `bind` methods write, SQLite by default. Set
+    /// it before binding anything.
+    pub fn backend(mut self, backend: Backend) -> Orm<State> {
+        self.backend = backend;
+        self
+    }
+
+    /// Adds `value` to the bound values and returns its placeholder, for the parts
+    /// of the query written by hand like [`Orm::<Where>::condition`]. `NULL` is
+    /// written as is, the `Any` driver would bind it as an integer that Postgres
+    /// refuses for other column types.
+    pub fn bind(&mut self, value: impl Into<Value>) -> String {
+        match value.into() {
+            Value::Null => "NULL".to_string(),
+            value => {
+                self.values.push(value);
+                self.backend.placeholder(self.values.len())
+            },
+        }
+    }
+
+    /// The finished query along with the values of its placeholders, in order.
+    pub fn build(&mut self) -> (String, Vec<Value>) {
+        (self.ready(), self.values.clone())
+    }
+
+    /// [`Orm::build`] with the values ready for `sqlx::query_with`.
+    pub fn build_arguments(&mut self) -> (String, AnyArguments<'static>) {
+        let (query, values) = self.build();
+        let mut arguments = AnyArguments::default();
+        for value in values {
+            value.add_to(&mut arguments);
+        }
+        (query, arguments)
+    }
+
     pub fn as_(self, short: &str) -> Orm<State> {
         Orm {
             query: format!("{} AS {}", self.query, short),
             has_where_clause: self.has_where_clause,
             state: PhantomData,
+            backend: self.backend,
+            values: self.values,
         }
     }
 
@@ -95,6 +269,8 @@ impl<State> Orm<State> {
             query: format!("{} AND", self.query),
             has_where_clause: self.has_where_clause,
             state: PhantomData,
+            backend: self.backend,
+            values: self.values,
         }
     }
 
@@ -103,6 +279,8 @@ impl<State> Orm<State> {
             query: format!("{} WHERE", self.query),
             has_where_clause: false,
             state: PhantomData,
+            backend: self.backend,
+            values: self.values.clone(),
         }
     }
 }
@@ -113,16 +291,30 @@ impl Orm<InsertColumns> {
             query: format!("{} ({columns}) VALUES", self.query),
             has_where_clause: false,
             state: PhantomData,
+            backend: self.backend,
+            values: self.values,
         }
     }
 }
 
 impl Orm<Insert> {
+    /// A row of placeholders for `row`, in the order of the columns.
+    pub fn bind_row(mut self, row: Vec<Value>) -> Orm<Insert> {
+        let placeholders = row
+            .into_iter()
+            .map(|value| self.bind(value))
+            .collect::<Vec<String>>()
+            .join(",");
+        self.add_value(&placeholders)
+    }
+
     pub fn add_value(self, values: &str) -> Orm<Insert> {
         Orm {
             query: format!("{} ({values}),", self.query),
             has_where_clause: false,
             state: PhantomData,
+            backend: self.backend,
+            values: self.values,
         }
     }
 
@@ -131,16 +323,59 @@ impl Orm<Insert> {
             query: format!("{} {values}", self.query),
             has_where_clause: false,
             state: PhantomData,
+            backend: self.backend,
+            values: self.values,
+        }
+    }
+
+    /// Postgres and SQLite flavour of [`Orm::insert_or_ignore`].
+    pub fn on_conflict_do_nothing(self) -> Orm<Insert> {
+        Orm {
+            query: format!(
+                "{} ON CONFLICT DO NOTHING",
+                self.query.trim_end_matches(',')
+            ),
+            has_where_clause: false,
+            state: PhantomData,
+            backend: self.backend,
+            values: self.values,
         }
     }
 }
 
+/// SQLite and Postgres syntax, MySQL needs each row written as `ROW(..)`.
+impl Orm<Values> {
+    pub fn row(self, row: &str) -> Orm<Values> {
+        Orm {
+            query: format!("{}, ({row})", self.query),
+            has_where_clause: false,
+            state: PhantomData,
+            backend: self.backend,
+            values: self.values,
+        }
+    }
+
+    /// The rows as a derived table for `FROM` or `JOIN`, `alias` can name the
+    /// columns as in `v(id, name)`, SQLite only accepts a bare name and calls the
+    /// columns `column1`, `column2`...
+    pub fn as_table(self, alias: &str) -> String {
+        format!("({}) AS {alias}", self.query)
+    }
+
+    /// The rows as a common table expression named `name`, e.g. `v(id, name)`.
+    pub fn as_cte(self, name: &str) -> String {
+        format!("{name} AS ({})", self.query)
+    }
+}
+
 impl Orm<Table> {
     pub fn drop_table(self, table: &str) -> Orm<Table> {
         Orm {
             query: format!("DROP TABLE {table}"),
             has_where_clause: false,
             state: PhantomData,
+            backend: self.backend,
+            values: self.values,
         }
     }
 
@@ -149,6 +384,8 @@ impl Orm<Table> {
             query: format!("TRUNCATE TABLE {table}"),
             has_where_clause: false,
             state: PhantomData,
+            backend: self.backend,
+            values: self.values,
         }
     }
 
@@ -157,6 +394,8 @@ impl Orm<Table> {
             query: format!("ALTER TABLE {table}"),
             has_where_clause: false,
             state: PhantomData,
+            backend: self.backend,
+            values: self.values,
         }
     }
 
@@ -166,6 +405,8 @@ impl Orm<Table> {
             query: format!("{} ADD {column} {data_type}", self.query),
             has_where_clause: self.has_where_clause,
             state: PhantomData,
+            backend: self.backend,
+            values: self.values,
         }
     }
 }
@@ -176,6 +417,8 @@ impl Orm<From> {
             query: format!("{} FROM {}", self.query, table),
             has_where_clause: self.has_where_clause,
             state: PhantomData,
+            backend: self.backend,
+            values: self.values,
         }
     }
     pub fn from_subquery(self, subquery: &str) -> Orm<From> {
@@ -183,6 +426,8 @@ impl Orm<From> {
             query: format!("{} FROM {}", self.query, subquery),
             has_where_clause: self.has_where_clause,
             state: PhantomData,
+            backend: self.backend,
+            values: self.values,
         }
     }
     pub fn join(self, table: &str) -> Orm<Join> {
@@ -190,6 +435,17 @@ impl Orm<From> {
             query: format!("{} JOIN {}", self.query, table),
             has_where_clause: self.has_where_clause,
             state: PhantomData,
+            backend: self.backend,
+            values: self.values,
+        }
+    }
+    pub fn order_by(self, columns: &str) -> Orm<From> {
+        Orm {
+            query: format!("{} ORDER BY {}", self.query, columns),
+            has_where_clause: self.has_where_clause,
+            state: PhantomData,
+            backend: self.backend,
+            values: self.values,
         }
     }
 }
@@ -200,6 +456,8 @@ impl Orm<Join> {
             query: format!("{} JOIN {}", self.query, table),
             has_where_clause: self.has_where_clause,
             state: PhantomData,
+            backend: self.backend,
+            values: self.values,
         }
     }
 
@@ -208,6 +466,8 @@ impl Orm<Join> {
             query: format!("{} LEFT JOIN {}", self.query, table),
             has_where_clause: self.has_where_clause,
             state: PhantomData,
+            backend: self.backend,
+            values: self.values,
         }
     }
 
@@ -216,6 +476,8 @@ impl Orm<Join> {
             query: format!("{} ON {}", self.query, on_clause),
             has_where_clause: self.has_where_clause,
             state: PhantomData,
+            backend: self.backend,
+            values: self.values,
         }
     }
 }
@@ -238,41 +500,42 @@ impl Orm<Where> {
     pub fn less_than(&mut self, column: &str, value: &str) -> &mut Self {
         self.and_for_where();
         self.query
-            .push_str(&format!("{} < {}", column, Self::correct_value(value)));
+            .push_str(&format!(" {} < {}", column, Self::correct_value(value)));
         self
     }
 
     pub fn greater_than(&mut self, column: &str, value: &str) -> &mut Self {
         self.and_for_where();
         self.query
-            .push_str(&format!("{} > {}", column, Self::correct_value(value)));
+            .push_str(&format!(" {} > {}", column, Self::correct_value(value)));
         self
     }
 
     pub fn less_than_or_equal(&mut self, column: &str, value: &str) -> &mut Self {
         self.and_for_where();
         self.query
-            .push_str(&format!("{} <= {}", column, Self::correct_value(value)));
+            .push_str(&format!(" {} <= {}", column, Self::correct_value(value)));
         self
     }
 
     pub fn greater_than_or_equal(&mut self, column: &str, value: &str) -> &mut Self {
         self.and_for_where();
         self.query
-            .push_str(&format!("{} >= {}", column, Self::correct_value(value)));
+            .push_str(&format!(" {} >= {}", column, Self::correct_value(value)));
         self
     }
 
     pub fn like(&mut self, column: &str, pattern: &str) -> &mut Self {
         self.and_for_where();
-        self.query.push_str(&format!("{} LIKE {}", column, pattern));
+        self.query
+            .push_str(&format!(" {} LIKE {}", column, pattern));
         self
     }
 
     pub fn not_like(&mut self, column: &str, pattern: &str) -> &mut Self {
         self.and_for_where();
         self.query
-            .push_str(&format!("{} NOT LIKE {}", column, pattern));
+            .push_str(&format!(" {} NOT LIKE {}", column, pattern));
         self
     }
 
@@ -284,7 +547,7 @@ impl Orm<Where> {
             .collect::<Vec<String>>()
             .join(", ");
         self.query
-            .push_str(&format!("{} IN ({})", column, value_list));
+            .push_str(&format!(" {} IN ({})", column, value_list));
         self
     }
 
@@ -296,19 +559,92 @@ impl Orm<Where> {
             .collect::<Vec<String>>()
             .join(", ");
         self.query
-            .push_str(&format!("{} NOT IN ({})", column, value_list));
+            .push_str(&format!(" {} NOT IN ({})", column, value_list));
+        self
+    }
+
+    pub fn condition(&mut self, condition: &str) -> &mut Self {
+        self.and_for_where();
+        self.query.push_str(&format!(" {}", condition));
+        self
+    }
+
+    pub fn order_by(&mut self, columns: &str) -> &mut Self {
+        self.query.push_str(&format!(" ORDER BY {}", columns));
+        self
+    }
+
+    pub fn limit(&mut self, limit: u32) -> &mut Self {
+        self.query.push_str(&format!(" LIMIT {}", limit));
         self
     }
 
     pub fn and_for_where(&mut self) -> &mut Self {
         if self.has_where_clause {
-            self.query.push_str(" AND ");
+            self.query.push_str(" AND");
         } else {
             self.has_where_clause = true;
         }
         self
     }
 
+    /// `column IS NULL` when `value` is [`Value::Null`], `= NULL` matches no row.
+    pub fn bind_equal(&mut self, column: &str, value: impl Into<Value>) -> &mut Self {
+        match value.into() {
+            Value::Null => self.condition(&format!("{column} IS NULL")),
+            value => self.bind_comparison(column, "=", value),
+        }
+    }
+
+    /// `column IS NOT NULL` when `value` is [`Value::Null`].
+    pub fn bind_not_equal(&mut self, column: &str, value: impl Into<Value>) -> &mut Self {
+        match value.into() {
+            Value::Null => self.condition(&format!("{column} IS NOT NULL")),
+            value => self.bind_comparison(column, "!=", value),
+        }
+    }
+
+    pub fn bind_less_than(&mut self, column: &str, value: impl Into<Value>) -> &mut Self {
+        self.bind_comparison(column, "<", value.into())
+    }
+
+    pub fn bind_greater_than(
+        &mut self,
+        column: &str,
+        value: impl Into<Value>,
+    ) -> &mut Self {
+        self.bind_comparison(column, ">", value.into())
+    }
+
+    pub fn bind_like(&mut self, column: &str, pattern: impl Into<Value>) -> &mut Self {
+        self.bind_comparison(column, "LIKE", pattern.into())
+    }
+
+    pub fn bind_in_values(&mut self, column: &str, values: Vec<Value>) -> &mut Self {
+        let placeholders = values
+            .into_iter()
+            .map(|value| self.bind(value))
+            .collect::<Vec<String>>()
+            .join(", ");
+        self.and_for_where();
+        self.query
+            .push_str(&format!(" {column} IN ({placeholders})"));
+        self
+    }
+
+    fn bind_comparison(
+        &mut self,
+        column: &str,
+        operator: &str,
+        value: Value,
+    ) -> &mut Self {
+        let placeholder = self.bind(value);
+        self.and_for_where();
+        self.query
+            .push_str(&format!(" {column} {operator} {placeholder}"));
+        self
+    }
+
     fn correct_value(value: &str) -> String {
         if value.parse::<f64>().is_ok() || value.parse::<i64>().is_ok() {
             return value.to_string();
@@ -323,6 +659,262 @@ impl Orm<Limit> {
             query: format!("{} LIMIT {}", self.query, limit),
             has_where_clause: self.has_where_clause,
             state: PhantomData,
+            backend: self.backend,
+            values: self.values.clone(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TestDb;
+
+    #[test]
+    fn test_values() {
+        let rows = Orm::values("1, 'a'").row("2, 'b'");
+        assert_eq!(
+            Orm::with(&rows.as_cte("v(id, name)"), "id, name")
+                .from("v")
+                .ready(),
+            "WITH v(id, name) AS (VALUES (1, 'a'), (2, 'b')) SELECT id, name FROM v;"
+        );
+
+        let rows = Orm::values("1").row("2");
+        assert_eq!(
+            Orm::select("users.name")
+                .from("users")
+                .join(&rows.as_table("ids(id)"))
+                .on("ids.id = users.id")
+                .ready(),
+            "SELECT users.name FROM users JOIN (VALUES (1), (2)) AS ids(id) ON ids.id = users.id;"
+        );
+    }
+
+    #[test]
+    fn test_ready_is_stable() {
+        let build = || {
+            Orm::insert("users")
+                .set_columns("id, name")
+                .add_value("1, 'ana'")
+                .add_value("2, 'bob'")
+        };
+        let mut insert = build();
+        let first = insert.ready();
+        assert_eq!(
+            first,
+            "INSERT INTO users (id, name) VALUES (1, 'ana'), (2, 'bob');"
+        );
+        assert_eq!(insert.ready(), first);
+        assert_eq!(build().ready(), first);
+
+        let mut select = Orm::select("id").from("users").where_();
+        select.in_values("name", &["ana", "bob"]).limit(1);
+        let first = select.ready();
+        assert_eq!(select.ready(), first);
+    }
+
+    /// Random builder chains from a seeded generator, rebuilt from the same seed to
+    /// check the text only depends on the chain.
+    #[test]
+    fn test_ready_is_stable_generated() {
+        fn chain(mut seed: u64) -> (Orm<Where>, Orm<Insert>) {
+            let mut next = move |bound: u64| {
+                seed = seed
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                (seed >> 33) % bound
+            };
+            let mut select = Orm::select("id").from("users").where_();
+            for _ in 0..=next(5) {
+                let column = ["id", "name", "age"][next(3) as usize];
+                match next(8) {
+                    0 => select.equal(column, "1"),
+                    1 => select.not_equal(column, "ana"),
+                    2 => select.less_than_or_equal(column, "2"),
+                    3 => select.like(column, "'a%'"),
+                    4 => select.in_values(column, &["1", "2"]),
+                    5 => select.bind_equal(column, next(3) as i64),
+                    6 => select.bind_in_values(column, vec![Value::Null, "bob".into()]),
+                    _ => select.condition("age IS NULL"),
+                };
+            }
+            if next(2) == 0 {
+                select.order_by("id");
+            }
+            if next(2) == 0 {
+                select.limit(next(10) as u32);
+            }
+
+            let mut insert = Orm::insert("users")
+                .set_columns("id, name")
+                .add_value("0, 'ana'");
+            for id in 1..=next(4) {
+                insert = match next(2) {
+                    0 => insert.add_value(&format!("{id}, 'bob'")),
+                    _ => insert.bind_row(vec![(id as i64).into(), Value::Null]),
+                };
+            }
+            (select, insert)
+        }
+
+        for seed in 0..200 {
+            let (mut select, mut insert) = chain(seed);
+            let (mut same_select, mut same_insert) = chain(seed);
+            let first = select.ready();
+            assert!(Backend::Sqlite.parse_sql(&first).is_ok(), "{first}");
+            assert_eq!(select.ready(), first, "seed {seed}");
+            assert_eq!(same_select.ready(), first, "seed {seed}");
+
+            let first = insert.ready();
+            assert!(Backend::Sqlite.parse_sql(&first).is_ok(), "{first}");
+            assert_eq!(insert.ready(), first, "seed {seed}");
+            assert_eq!(same_insert.ready(), first, "seed {seed}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_bound_queries() {
+        let db = TestDb::new(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT, age INTEGER);",
+        )
+        .await
+        .unwrap();
+        let (query, arguments) = Orm::insert("users")
+            .set_columns("id, name, age")
+            .bind_row(vec![1.into(), "o'hara".into(), Value::Null])
+            .bind_row(vec![2.into(), "bob; DROP TABLE users".into(), 17.into()])
+            .build_arguments();
+        assert_eq!(
+            query,
+            "INSERT INTO users (id, name, age) VALUES (?,?,NULL), (?,?,?);"
+        );
+        sqlx::query_with(&query, arguments)
+            .execute(db.pool())
+            .await
+            .unwrap();
+
+        let (query, arguments) = Orm::update("users")
+            .bind_set(&[("age", 18.into())])
+            .where_()
+            .bind_equal("name", "o'hara")
+            .build_arguments();
+        let updated = sqlx::query_with(&query, arguments)
+            .execute(db.pool())
+            .await
+            .unwrap();
+        assert_eq!(updated.rows_affected(), 1);
+
+        let mut select = Orm::select("name").from("users").where_();
+        select
+            .bind_in_values("id", vec![1.into(), 2.into()])
+            .bind_greater_than("age", 17)
+            .bind_not_equal("name", None::<String>);
+        let (query, values) = select.build();
+        assert_eq!(
+            query,
+            "SELECT name FROM users WHERE id IN (?, ?) AND age > ? AND name IS NOT NULL;"
+        );
+        assert_eq!(values, [Value::Int(1), Value::Int(2), Value::Int(17)]);
+        let (query, arguments) = select.build_arguments();
+        let names: Vec<String> = sqlx::query_scalar_with(&query, arguments)
+            .fetch_all(db.pool())
+            .await
+            .unwrap();
+        assert_eq!(names, ["o'hara"]);
+
+        db.execute("INSERT INTO users (id, name) VALUES (3, 'cleo');")
+            .await
+            .unwrap();
+        let (query, arguments) = Orm::select("id")
+            .from("users")
+            .where_()
+            .bind_equal("age", None::<i64>)
+            .build_arguments();
+        assert_eq!(query, "SELECT id FROM users WHERE age IS NULL;");
+        let ids: Vec<i64> = sqlx::query_scalar_with(&query, arguments)
+            .fetch_all(db.pool())
+            .await
+            .unwrap();
+        assert_eq!(ids, [3]);
+
+        #[cfg(feature = "postgres")]
+        assert_eq!(
+            Orm::delete("FROM users")
+                .backend(Backend::Postgres)
+                .where_()
+                .bind_equal("id", 1)
+                .bind_like("name", "b%")
+                .build()
+                .0,
+            "DELETE FROM users WHERE id = $1 AND name LIKE $2;"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_queries_run() {
+        let db = TestDb::new(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT NOT NULL, age INTEGER);
+             CREATE TABLE posts (id INTEGER PRIMARY KEY, user_id INTEGER, title TEXT);",
+        )
+        .await
+        .unwrap();
+        let insert = Orm::insert("users")
+            .set_columns("id, name, age")
+            .add_value("1, 'ana', 31")
+            .add_value("2, 'bob', 17")
+            .add_value("3, 'cleo', NULL")
+            .ready();
+        assert_eq!(db.execute(&insert).await.unwrap(), 3);
+        let insert = Orm::insert("posts")
+            .set_columns("id, user_id, title")
+            .add_value("1, 1, 'hello'")
+            .on_conflict_do_nothing()
+            .ready();
+        assert_eq!(db.execute(&insert).await.unwrap(), 1);
+        assert_eq!(db.execute(&insert).await.unwrap(), 0);
+
+        let query = Orm::select("name")
+            .from("users")
+            .where_()
+            .greater_than_or_equal("age", "18")
+            .not_like("name", "'b%'")
+            .ready();
+        db.assert_rows(&query, &[&["ana"]]).await;
+        let query = Orm::select("id")
+            .from("users")
+            .where_()
+            .in_values("name", &["bob", "cleo"])
+            .less_than("id", "3")
+            .ready();
+        db.assert_rows(&query, &[&["2"]]).await;
+        let query = Orm::select("users.name, posts.title")
+            .from("users")
+            .join("posts")
+            .left_join("posts AS p2")
+            .on("p2.user_id = users.id")
+            .where_()
+            .condition("posts.user_id = users.id")
+            .ready();
+        db.assert_rows(&query, &[&["ana", "hello"]]).await;
+
+        let update = Orm::update("users")
+            .set("age = 18")
+            .where_()
+            .equal("name", "bob")
+            .ready();
+        assert_eq!(db.execute(&update).await.unwrap(), 1);
+        let delete = Orm::delete("FROM users")
+            .where_()
+            .not_equal("age", "18")
+            .ready();
+        assert_eq!(db.execute(&delete).await.unwrap(), 1);
+        let query = Orm::select("name")
+            .from("users")
+            .where_()
+            .condition("age IS NULL OR age = 18")
+            .order_by("id")
+            .ready();
+        db.assert_rows(&query, &[&["bob"], &["cleo"]]).await;
+    }
+}
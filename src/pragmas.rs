@@ -0,0 +1,150 @@
+use std::{sync::Arc, time::Duration};
+
+use sqlx::{
+    any::{install_default_drivers, AnyPoolOptions},
+    AnyPool, Executor,
+};
+use tracing::{error, warn};
+
+use super::migrations::retry_connect;
+
+/// Pragmas run on every SQLite connection the migrations open, so they don't depend
+/// on the defaults of the driver: without `foreign_keys = ON` a migration rebuilding
+/// a table silently skips the cascades it would otherwise trigger. From
+/// `SQLITE_PRAGMAS`, e.g. `journal_mode=WAL,foreign_keys=ON`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SqlitePragmas {
+    pragmas: Vec<(String, String)>,
+}
+
+impl SqlitePragmas {
+    pub(crate) fn from_env() -> Self {
+        let value = std::env::var("SQLITE_PRAGMAS").unwrap_or_default();
+        let mut pragmas = Self::default();
+        for pragma in value.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            match pragma.split_once('=') {
+                Some((name, value)) if is_safe(name.trim()) && is_safe(value.trim()) => {
+                    pragmas = pragmas.set(name.trim(), value.trim());
+                },
+                _ => warn!(
+                    function = "SqlitePragmas::from_env",
+                    message =
+                        format!("Ignoring the invalid SQLITE_PRAGMAS entry {pragma}"),
+                ),
+            }
+        }
+        pragmas
+    }
+
+    /// `DELETE`, `TRUNCATE`, `PERSIST`, `MEMORY`, `WAL` or `OFF`.
+    pub fn journal_mode(self, mode: &str) -> Self {
+        self.set("journal_mode", mode)
+    }
+
+    pub fn foreign_keys(self, enabled: bool) -> Self {
+        self.set("foreign_keys", if enabled { "ON" } else { "OFF" })
+    }
+
+    pub fn busy_timeout(self, timeout: Duration) -> Self {
+        self.set("busy_timeout", &timeout.as_millis().to_string())
+    }
+
+    /// `OFF`, `NORMAL`, `FULL` or `EXTRA`.
+    pub fn synchronous(self, level: &str) -> Self {
+        self.set("synchronous", level)
+    }
+
+    /// Replaces the value of `name` if it was already set. Values that aren't a single
+    /// word or number are ignored, they would end up in the statement as is.
+    fn set(mut self, name: &str, value: &str) -> Self {
+        if !is_safe(value) {
+            warn!(
+                function = "SqlitePragmas::set",
+                message = format!("Ignoring the invalid value {value} of {name}"),
+            );
+            return self;
+        }
+        self.pragmas.retain(|(existing, _)| existing != name);
+        self.pragmas.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.pragmas.is_empty()
+    }
+
+    fn statements(&self) -> Vec<String> {
+        self.pragmas
+            .iter()
+            .map(|(name, value)| format!("PRAGMA {name} = {value};"))
+            .collect()
+    }
+
+    /// Pool on the SQLite database of `db_url` running the pragmas on each connection
+    /// it opens, retrying for `timeout` while it can't be opened.
+    pub(crate) async fn connect(
+        &self,
+        db_url: &str,
+        timeout: Duration,
+    ) -> Result<AnyPool, sqlx::Error> {
+        install_default_drivers();
+        let statements = Arc::new(self.statements());
+        let pool = retry_connect(timeout, || {
+            let statements = statements.clone();
+            AnyPoolOptions::new()
+                .after_connect(move |connection, _| {
+                    let statements = statements.clone();
+                    Box::pin(async move {
+                        for statement in statements.iter() {
+                            connection.execute(statement.as_str()).await?;
+                        }
+                        Ok(())
+                    })
+                })
+                .connect(db_url)
+        })
+        .await;
+        pool.inspect_err(|err| {
+            error!(
+                function = "SqlitePragmas::connect",
+                error_message = format!("{err}"),
+                message = "Error connecting to database",
+            );
+        })
+    }
+}
+
+fn is_safe(value: &str) -> bool {
+    !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_sqlite_pragmas() {
+        let pragmas = SqlitePragmas::default()
+            .foreign_keys(true)
+            .synchronous("NORMAL; DROP TABLE users")
+            .busy_timeout(Duration::from_secs(2))
+            .foreign_keys(false);
+        assert_eq!(
+            pragmas.statements(),
+            ["PRAGMA busy_timeout = 2000;", "PRAGMA foreign_keys = OFF;"]
+        );
+
+        let pool = pragmas
+            .connect("sqlite::memory:", Duration::ZERO)
+            .await
+            .unwrap();
+        let foreign_keys: i64 = sqlx::query_scalar("PRAGMA foreign_keys;")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(foreign_keys, 0);
+    }
+}
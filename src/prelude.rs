@@ -0,0 +1,6 @@
+//! The items most code using aromatic needs, `use aromatic::prelude::*;`.
+
+pub use crate::{
+    embed_migrations, AromaticError, EmbeddedMigration, Migration, MigrationReport,
+    Migrator, MigratorBuilder, Orm,
+};
@@ -0,0 +1,53 @@
+#[cfg(feature = "mysql")]
+use sqlx::mysql::MySqlDatabaseError;
+use sqlx::Executor;
+
+use super::{
+    migrations::{connect, database_url},
+    AromaticError, Backend,
+};
+
+const PROBE_TABLE: &str = "aromatic_privilege_probe";
+
+/// Fails if the application user, the one of `DATABASE_URL`, is allowed to create
+/// tables. With migrations running as their own role from `MIGRATION_DATABASE_URL`
+/// the application should only be able to read and write data.
+///
+/// A table is created and dropped inside a transaction that is rolled back, only a
+/// permission error creating it means the user can't. SQLite has no users, only file
+/// permissions, so it can't be checked.
+pub async fn check_app_privileges() -> Result<(), AromaticError> {
+    let (db_url, backend) = database_url()?;
+    if backend == Backend::Sqlite {
+        return Err(AromaticError::Config(
+            "SQLite has no users, there are no privileges to check".to_string(),
+        ));
+    }
+    let pool = connect(&db_url).await?;
+    let mut transaction = pool.begin().await?;
+    let create = format!("CREATE TABLE {PROBE_TABLE} (id INTEGER);");
+    match transaction.execute(create.as_str()).await {
+        Ok(_) => (),
+        Err(err) if is_permission_denied(&err) => return Ok(()),
+        Err(err) => return Err(err.into()),
+    }
+    // MySQL commits DDL right away, the rollback alone wouldn't remove it
+    transaction
+        .execute(format!("DROP TABLE {PROBE_TABLE};").as_str())
+        .await?;
+    transaction.rollback().await?;
+    Err(AromaticError::AppUserCanChangeSchema)
+}
+
+/// `42501` on Postgres, 1142 (on the table) and 1044 (on the database) on MySQL,
+/// whose SQLSTATE for them is shared with syntax errors.
+fn is_permission_denied(err: &sqlx::Error) -> bool {
+    let Some(err) = err.as_database_error() else {
+        return false;
+    };
+    #[cfg(feature = "mysql")]
+    if let Some(err) = err.try_downcast_ref::<MySqlDatabaseError>() {
+        return matches!(err.number(), 1142 | 1044);
+    }
+    err.code().as_deref() == Some("42501")
+}
@@ -0,0 +1,166 @@
+use std::time::{Duration, Instant};
+
+use sqlx::AnyPool;
+use tracing::error;
+
+use super::{
+    config::RetentionPolicy,
+    migrations::{connect, database_url},
+    AromaticError, Backend,
+};
+
+pub const DEFAULT_BATCH_SIZE: u32 = 1000;
+
+/// Slows batched work down so it doesn't starve the application sharing the database:
+/// after each batch it waits `pause`, or longer when needed to stay under
+/// `max_rows_per_second`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Throttle {
+    /// `0` is no limit, like `None`.
+    pub max_rows_per_second: Option<u32>,
+    pub pause: Duration,
+}
+
+impl Throttle {
+    /// How long to wait after a batch of `rows` rows that took `elapsed`.
+    fn delay(&self, rows: u64, elapsed: Duration) -> Duration {
+        let rate = self.max_rows_per_second.filter(|rate| *rate > 0);
+        let paced = rate.map_or(Duration::ZERO, |rate| {
+            Duration::from_secs_f64(rows as f64 / f64::from(rate)).saturating_sub(elapsed)
+        });
+        paced.max(self.pause)
+    }
+}
+
+impl RetentionPolicy {
+    /// Statement deleting one batch of expired rows, run it until it affects fewer
+    /// than `batch_size` rows to purge them all.
+    pub fn cleanup_sql(&self, backend: Backend, batch_size: u32) -> String {
+        let condition = backend.older_than(&self.column, self.max_age_days);
+        backend.delete_batch(&self.table, &condition, batch_size)
+    }
+}
+
+/// Deletes the expired rows of every policy, `batch_size` rows per statement so large
+/// tables are not locked for the whole purge. `progress` is called after each batch
+/// with the policy and the number of rows deleted so far, the totals are returned in
+/// the same order as `policies`.
+pub async fn cleanup(
+    policies: &[RetentionPolicy],
+    batch_size: u32,
+    throttle: Throttle,
+    mut progress: impl FnMut(&RetentionPolicy, u64),
+) -> Result<Vec<u64>, AromaticError> {
+    check_batch_size(batch_size)?;
+    let (db_url, backend) = database_url()?;
+    let pool = connect(&db_url).await?;
+    let mut totals = Vec::with_capacity(policies.len());
+    for policy in policies {
+        let query = policy.cleanup_sql(backend, batch_size);
+        let total = run_batches(&pool, &query, batch_size, throttle, |total| {
+            progress(policy, total)
+        })
+        .await
+        .inspect_err(|err| {
+            error!(
+                function = "cleanup",
+                error_message = format!("{err}"),
+                message = format!("Could not clean up {}", policy.table),
+            );
+        })?;
+        totals.push(total);
+    }
+    Ok(totals)
+}
+
+/// Runs `sql` over and over, each time in a transaction of its own, until it affects
+/// fewer than `batch_size` rows, e.g. `UPDATE users SET email = lower(email) WHERE id IN
+/// (SELECT id FROM users WHERE email <> lower(email) LIMIT 1000)`. Meant for the data
+/// of hot tables, which a single statement or a migration's transaction would lock
+/// until it is done. Returns the number of rows affected.
+pub async fn backfill(
+    pool: &AnyPool,
+    sql: &str,
+    batch_size: u32,
+    throttle: Throttle,
+) -> Result<u64, AromaticError> {
+    check_batch_size(batch_size)?;
+    let total = run_batches(pool, sql, batch_size, throttle, |_| ())
+        .await
+        .inspect_err(|err| {
+            error!(
+                function = "backfill",
+                error_message = format!("{err}"),
+                message = "Could not run the backfill",
+            );
+        })?;
+    Ok(total)
+}
+
+/// A batch of 0 rows never ends the loop of [`run_batches`].
+fn check_batch_size(batch_size: u32) -> Result<(), AromaticError> {
+    match batch_size {
+        0 => Err(AromaticError::Config(
+            "the batch size must be at least 1".to_string(),
+        )),
+        _ => Ok(()),
+    }
+}
+
+async fn run_batches(
+    pool: &AnyPool,
+    sql: &str,
+    batch_size: u32,
+    throttle: Throttle,
+    mut progress: impl FnMut(u64),
+) -> Result<u64, sqlx::Error> {
+    let mut total = 0;
+    loop {
+        let started = Instant::now();
+        let affected = sqlx::query(sql).execute(pool).await?.rows_affected();
+        total += affected;
+        progress(total);
+        if affected < u64::from(batch_size) {
+            return Ok(total);
+        }
+        tokio::time::sleep(throttle.delay(affected, started.elapsed())).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TestDb;
+
+    #[tokio::test]
+    async fn test_backfill_rejects_empty_batches() {
+        let db = TestDb::new("CREATE TABLE users (id INTEGER);")
+            .await
+            .unwrap();
+        let result = backfill(
+            db.pool(),
+            "UPDATE users SET id = id;",
+            0,
+            Throttle::default(),
+        )
+        .await;
+        assert!(matches!(result, Err(AromaticError::Config(_))));
+    }
+
+    #[test]
+    fn test_throttle_delay() {
+        let second = Duration::from_secs(1);
+        assert_eq!(Throttle::default().delay(1000, second), Duration::ZERO);
+        let throttle = Throttle {
+            max_rows_per_second: Some(500),
+            pause: Duration::from_millis(100),
+        };
+        assert_eq!(throttle.delay(1000, second), second);
+        assert_eq!(throttle.delay(1000, 3 * second), Duration::from_millis(100));
+        let unlimited = Throttle {
+            max_rows_per_second: Some(0),
+            pause: Duration::ZERO,
+        };
+        assert_eq!(unlimited.delay(1000, second), Duration::ZERO);
+    }
+}
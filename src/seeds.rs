@@ -0,0 +1,185 @@
+use std::path::{Path, PathBuf};
+
+use sqlx::{AnyConnection, Connection, Executor};
+use tracing::error;
+
+use super::{
+    migrations::{connect, migration_database_url, read_folder},
+    AromaticError, Backend, Orm,
+};
+
+pub(crate) const SEEDS_TABLE: &str = "seeds";
+
+/// Seeds without this line run once, those with it run on every call.
+const REPEATABLE_MARKER: &str = "-- repeatable";
+
+const SEEDS_TABLE_DDL: &str = "CREATE TABLE IF NOT EXISTS seeds (
+    name TEXT NOT NULL,
+    env TEXT,
+    timestamp TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);";
+
+/// A `.sql` file of the seeds folder, named by its path relative to it.
+#[derive(Debug)]
+struct SeedFile {
+    name: String,
+    path: PathBuf,
+    env: Option<String>,
+}
+
+/// Environment the seeds are run for, from `SEED_ENV`.
+fn seed_env() -> Option<String> {
+    std::env::var("SEED_ENV")
+        .ok()
+        .filter(|value| !value.is_empty())
+}
+
+/// Runs the seeds of `folder` on the migrations database: the `.sql` files at its
+/// root and, when `SEED_ENV` is set, those of the subfolder named after it, e.g.
+/// `seeds/dev/users.sql`. Each seed runs in its own transaction and is recorded in
+/// the `seeds` table, so it is skipped afterwards unless it holds a `-- repeatable`
+/// line. Returns the names of the seeds that ran.
+pub async fn seed(folder: &str) -> Result<Vec<String>, AromaticError> {
+    let env = seed_env();
+    let files = seed_files(Path::new(folder), env.as_deref()).await?;
+    let (db_url, backend) = migration_database_url()?;
+    let pool = connect(&db_url).await?;
+    let mut connection = pool.acquire().await?;
+    connection.execute(SEEDS_TABLE_DDL).await?;
+    let query = Orm::select(&backend.cast_to_text("name"))
+        .from(SEEDS_TABLE)
+        .ready();
+    let seeded: Vec<String> = sqlx::query_scalar(&query)
+        .fetch_all(&mut *connection)
+        .await?;
+    let mut ran = Vec::new();
+    for file in files {
+        let sql = tokio::fs::read_to_string(&file.path)
+            .await
+            .map_err(|err| AromaticError::io(&file.path, err))?;
+        let repeatable = sql
+            .lines()
+            .any(|line| line.trim().eq_ignore_ascii_case(REPEATABLE_MARKER));
+        if !repeatable && seeded.contains(&file.name) {
+            continue;
+        }
+        if let Err(err) = run_seed(backend, &file, &sql, &mut connection).await {
+            error!(
+                function = "seed",
+                error_message = format!("{err}"),
+                message = format!("Could not run the seed {}", file.name),
+            );
+            return Err(AromaticError::Seed {
+                name: file.name,
+                source: err,
+            });
+        }
+        ran.push(file.name);
+    }
+    Ok(ran)
+}
+
+async fn run_seed(
+    backend: Backend,
+    file: &SeedFile,
+    sql: &str,
+    connection: &mut AnyConnection,
+) -> Result<(), sqlx::Error> {
+    let mut transaction = connection.begin().await?;
+    transaction.execute(sql).await?;
+    let (delete, arguments) = Orm::delete(&format!("FROM {SEEDS_TABLE}"))
+        .backend(backend)
+        .where_()
+        .bind_equal("name", file.name.as_str())
+        .build_arguments();
+    sqlx::query_with(&delete, arguments)
+        .execute(&mut *transaction)
+        .await?;
+    let (insert, arguments) = Orm::insert(SEEDS_TABLE)
+        .backend(backend)
+        .set_columns("name,env")
+        .bind_row(vec![file.name.as_str().into(), file.env.clone().into()])
+        .build_arguments();
+    sqlx::query_with(&insert, arguments)
+        .execute(&mut *transaction)
+        .await?;
+    transaction.commit().await
+}
+
+/// The shared seeds first then those of `env`, each group in name order.
+async fn seed_files(
+    folder: &Path,
+    env: Option<&str>,
+) -> Result<Vec<SeedFile>, AromaticError> {
+    let paths = read_folder(&folder.display().to_string())
+        .await
+        .map_err(|err| AromaticError::io(folder, err))?;
+    let mut files = sql_files(paths, "", None);
+    if let Some(env) = env {
+        let env_folder = folder.join(env);
+        if env_folder.is_dir() {
+            let paths = read_folder(&env_folder.display().to_string())
+                .await
+                .map_err(|err| AromaticError::io(&env_folder, err))?;
+            files.extend(sql_files(paths, &format!("{env}/"), Some(env)));
+        }
+    }
+    Ok(files)
+}
+
+fn sql_files(paths: Vec<PathBuf>, prefix: &str, env: Option<&str>) -> Vec<SeedFile> {
+    let mut files: Vec<SeedFile> = paths
+        .into_iter()
+        .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "sql"))
+        .filter_map(|path| {
+            let name = format!("{prefix}{}", path.file_name()?.to_str()?);
+            Some(SeedFile {
+                name,
+                path,
+                env: env.map(str::to_string),
+            })
+        })
+        .collect();
+    files.sort_by(|a, b| a.name.cmp(&b.name));
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{create_dir_all, remove_dir_all, write};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_seed_files() {
+        let root = std::env::temp_dir().join("aromatic_test_seed_files");
+        create_dir_all(root.join("dev")).unwrap();
+        create_dir_all(root.join("prod")).unwrap();
+        for file in [
+            "b.sql",
+            "a.sql",
+            "notes.md",
+            "dev/users.sql",
+            "prod/admin.sql",
+        ] {
+            write(root.join(file), "").unwrap();
+        }
+
+        let names = |files: Vec<SeedFile>| -> Vec<String> {
+            files.into_iter().map(|file| file.name).collect()
+        };
+        assert_eq!(
+            names(seed_files(&root, None).await.unwrap()),
+            ["a.sql", "b.sql"]
+        );
+        let files = seed_files(&root, Some("dev")).await.unwrap();
+        assert_eq!(files[2].env.as_deref(), Some("dev"));
+        assert_eq!(names(files), ["a.sql", "b.sql", "dev/users.sql"]);
+        assert_eq!(
+            names(seed_files(&root, Some("staging")).await.unwrap()),
+            ["a.sql", "b.sql"]
+        );
+
+        remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,175 @@
+use std::ops::Range;
+
+use super::Backend;
+
+/// Byte ranges of the statements of `sql`, each one starting at its first token and
+/// ending with its `;` when there is one. Semicolons inside quotes, comments, Postgres
+/// dollar quoted bodies and the `BEGIN ... END` body of a trigger don't end a
+/// statement, on MySQL a backslash escapes the quote following it. Comments between
+/// statements are left out.
+pub(crate) fn split_statements(backend: Backend, sql: &str) -> Vec<Range<usize>> {
+    let bytes = sql.as_bytes();
+    let mut statements = Vec::new();
+    let mut start = None;
+    let mut words = Vec::new();
+    let mut depth = 0usize;
+    let mut i = 0;
+    while i < bytes.len() {
+        let byte = bytes[i];
+        let next = bytes.get(i + 1).copied();
+        if byte == b'-' && next == Some(b'-') {
+            i = find(bytes, i, b"\n").map_or(bytes.len(), |end| end + 1);
+            continue;
+        }
+        if byte == b'/' && next == Some(b'*') {
+            i = find(bytes, i + 2, b"*/").map_or(bytes.len(), |end| end + 2);
+            continue;
+        }
+        if byte.is_ascii_whitespace() {
+            i += 1;
+            continue;
+        }
+        let statement_start = *start.get_or_insert(i);
+        match byte {
+            b'\'' | b'"' => {
+                let escapes = backend.backslash_escapes();
+                i = closing_quote(bytes, i + 1, byte, escapes)
+                    .map_or(bytes.len(), |end| end + 1);
+            },
+            b'`' => {
+                i = find(bytes, i + 1, &[byte]).map_or(bytes.len(), |end| end + 1);
+            },
+            b'$' => match dollar_tag(&bytes[i..]) {
+                Some(tag) => {
+                    let body = i + tag.len();
+                    i = find(bytes, body, tag).map_or(bytes.len(), |end| end + tag.len());
+                },
+                None => i += 1,
+            },
+            b';' if depth == 0 => {
+                statements.push(statement_start..i + 1);
+                start = None;
+                words.clear();
+                i += 1;
+            },
+            _ if is_word_start(byte) && (i == 0 || !is_word_byte(bytes[i - 1])) => {
+                let end = (i..bytes.len())
+                    .find(|&j| !is_word_byte(bytes[j]))
+                    .unwrap_or(bytes.len());
+                let word = sql[i..end].to_ascii_uppercase();
+                if is_trigger(&words) {
+                    match word.as_str() {
+                        "BEGIN" | "CASE" => depth += 1,
+                        "END" => depth = depth.saturating_sub(1),
+                        _ => (),
+                    }
+                }
+                if words.len() < 4 {
+                    words.push(word);
+                }
+                i = end;
+            },
+            _ => i += 1,
+        }
+    }
+    if let Some(start) = start {
+        statements.push(start..sql.trim_end().len().max(start));
+    }
+    statements
+}
+
+/// `CREATE [TEMP] TRIGGER`, whose body holds statements of its own.
+fn is_trigger(words: &[String]) -> bool {
+    words.first().is_some_and(|word| word == "CREATE")
+        && words.iter().skip(1).any(|word| word == "TRIGGER")
+}
+
+/// `$$` or `$tag$` at the start of `bytes`, not a `$1` parameter.
+fn dollar_tag(bytes: &[u8]) -> Option<&[u8]> {
+    if bytes.get(1).is_some_and(u8::is_ascii_digit) {
+        return None;
+    }
+    let end = bytes[1..].iter().position(|byte| !is_word_byte(*byte))? + 1;
+    (bytes[end] == b'$').then(|| &bytes[..=end])
+}
+
+/// Position of the `quote` closing a string starting at `from`, skipping the
+/// characters escaped with a backslash when `escapes` is set.
+fn closing_quote(bytes: &[u8], from: usize, quote: u8, escapes: bool) -> Option<usize> {
+    let mut i = from;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' if escapes => i += 2,
+            byte if byte == quote => return Some(i),
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+fn find(bytes: &[u8], from: usize, needle: &[u8]) -> Option<usize> {
+    bytes
+        .get(from..)?
+        .windows(needle.len())
+        .position(|window| window == needle)
+        .map(|position| from + position)
+}
+
+fn is_word_start(byte: u8) -> bool {
+    byte.is_ascii_alphabetic() || byte == b'_'
+}
+
+fn is_word_byte(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || byte == b'_'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(sql: &str) -> Vec<&str> {
+        split_statements(Backend::Sqlite, sql)
+            .into_iter()
+            .map(|range| &sql[range])
+            .collect()
+    }
+
+    #[test]
+    fn test_split_statements() {
+        assert_eq!(
+            split(
+                "-- users\nCREATE TABLE a (name TEXT DEFAULT ';');\n\n\
+                 /* ; */ INSERT INTO a VALUES ('it''s; fine');\nSELECT 1"
+            ),
+            [
+                "CREATE TABLE a (name TEXT DEFAULT ';');",
+                "INSERT INTO a VALUES ('it''s; fine');",
+                "SELECT 1",
+            ]
+        );
+        assert!(split("-- only a comment\n").is_empty());
+
+        // only MySQL escapes quotes with a backslash
+        let escaped = "INSERT INTO t VALUES ('it\\'s; fine');";
+        assert_eq!(
+            split(escaped),
+            ["INSERT INTO t VALUES ('it\\'s;", "fine');"]
+        );
+        #[cfg(feature = "mysql")]
+        assert_eq!(split_statements(Backend::MySql, escaped).len(), 1);
+    }
+
+    #[test]
+    fn test_split_statements_bodies() {
+        let trigger = "CREATE TRIGGER a_log AFTER INSERT ON a BEGIN\n  \
+                       INSERT INTO log VALUES (CASE WHEN new.id > 1 THEN 1 ELSE 0 END);\n\
+                       END;";
+        assert_eq!(split(&format!("{trigger}\nBEGIN;")), [trigger, "BEGIN;"]);
+        let function = "CREATE FUNCTION f() RETURNS int AS $body$ SELECT 1; $body$ \
+                        LANGUAGE sql;";
+        assert_eq!(
+            split(&format!("{function} SELECT $1;")),
+            [function, "SELECT $1;"]
+        );
+    }
+}
@@ -0,0 +1,71 @@
+use sqlx::{
+    any::{install_default_drivers, AnyPoolOptions, AnyRow},
+    AnyPool, Executor, Row, TypeInfo, ValueRef,
+};
+
+/// Throwaway in-memory SQLite database to check that queries built with [`Orm`]
+/// run and return what they should. Enabled by the `testing` feature.
+///
+/// [`Orm`]: crate::Orm
+pub struct TestDb {
+    pool: AnyPool,
+}
+
+impl TestDb {
+    /// New empty database with the tables of `ddl`, which can hold several
+    /// statements.
+    pub async fn new(ddl: &str) -> Result<Self, sqlx::Error> {
+        install_default_drivers();
+        // Every connection to `sqlite::memory:` opens its own database, so the pool
+        // keeps a single one alive for the whole test.
+        let pool = AnyPoolOptions::new()
+            .min_connections(1)
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect("sqlite::memory:")
+            .await?;
+        pool.execute(ddl).await?;
+        Ok(Self { pool })
+    }
+
+    pub fn pool(&self) -> &AnyPool {
+        &self.pool
+    }
+
+    /// Runs `query` and returns the number of rows it changed.
+    pub async fn execute(&self, query: &str) -> Result<u64, sqlx::Error> {
+        Ok(self.pool.execute(query).await?.rows_affected())
+    }
+
+    /// Runs `query` and returns every value as text, `NULL` for nulls.
+    pub async fn rows(&self, query: &str) -> Result<Vec<Vec<String>>, sqlx::Error> {
+        let rows = sqlx::query(query).fetch_all(&self.pool).await?;
+        rows.iter().map(row_as_text).collect()
+    }
+
+    /// Panics, showing the query, if it fails or doesn't return `expected`.
+    pub async fn assert_rows(&self, query: &str, expected: &[&[&str]]) {
+        let rows = match self.rows(query).await {
+            Ok(rows) => rows,
+            Err(err) => panic!("{query} failed: {err}"),
+        };
+        assert_eq!(rows, expected, "unexpected rows for {query}");
+    }
+}
+
+fn row_as_text(row: &AnyRow) -> Result<Vec<String>, sqlx::Error> {
+    (0..row.len())
+        .map(|i| {
+            let type_name = row.try_get_raw(i)?.type_info().name().to_string();
+            Ok(match type_name.as_str() {
+                "NULL" => "NULL".to_string(),
+                "BIGINT" | "INTEGER" | "INT" | "BOOLEAN" => {
+                    row.try_get::<i64, _>(i)?.to_string()
+                },
+                "DOUBLE" | "REAL" | "FLOAT" => row.try_get::<f64, _>(i)?.to_string(),
+                _ => row.try_get::<String, _>(i)?,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,327 @@
+use super::Backend;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Kind {
+    UpdatedAt,
+    Audit { history: String },
+    CascadeSoftDelete { child: String, foreign_key: String },
+}
+
+/// Common triggers written once for every database: keeping an `updated_at` column
+/// current, logging changes into a history table and soft deleting children with
+/// their parent. [`Trigger::create`] gives the statements for a migration file or a
+/// [`Migration`](crate::Migration), [`Trigger::drop`] the ones reverting them.
+///
+/// ```ignore
+/// let statements = Trigger::updated_at("posts").create(Backend::Sqlite);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trigger {
+    kind: Kind,
+    table: String,
+    column: String,
+    key: String,
+    name: Option<String>,
+}
+
+impl Trigger {
+    /// Sets the `updated_at` column of `table` to the current time on every update.
+    pub fn updated_at(table: &str) -> Self {
+        Self::new(Kind::UpdatedAt, table, "updated_at")
+    }
+
+    /// Inserts the table name, the operation and the key of every inserted, updated
+    /// and deleted row of `table` into `history`, which needs `table_name`,
+    /// `operation` and `row_key` text columns and fills its own timestamp.
+    pub fn audit(table: &str, history: &str) -> Self {
+        let history = history.to_string();
+        Self::new(Kind::Audit { history }, table, "")
+    }
+
+    /// Sets the `deleted_at` column of the rows of `child` referencing a row of
+    /// `parent` through `foreign_key` when that row is soft deleted.
+    pub fn cascade_soft_delete(parent: &str, child: &str, foreign_key: &str) -> Self {
+        let kind = Kind::CascadeSoftDelete {
+            child: child.to_string(),
+            foreign_key: foreign_key.to_string(),
+        };
+        Self::new(kind, parent, "deleted_at")
+    }
+
+    fn new(kind: Kind, table: &str, column: &str) -> Self {
+        Self {
+            kind,
+            table: table.to_string(),
+            column: column.to_string(),
+            key: "id".to_string(),
+            name: None,
+        }
+    }
+
+    /// Timestamp column of [`Trigger::updated_at`] and [`Trigger::cascade_soft_delete`].
+    pub fn column(mut self, column: &str) -> Self {
+        self.column = column.to_string();
+        self
+    }
+
+    /// Primary key of the table, `id` by default.
+    pub fn key(mut self, key: &str) -> Self {
+        self.key = key.to_string();
+        self
+    }
+
+    /// Name of the trigger, by default the table followed by what it does.
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    fn trigger_name(&self) -> String {
+        self.name.clone().unwrap_or_else(|| {
+            let suffix = match &self.kind {
+                Kind::UpdatedAt => "updated_at",
+                Kind::Audit { .. } => "audit",
+                Kind::CascadeSoftDelete { child, .. } => child,
+            };
+            format!("{}_{suffix}", self.table)
+        })
+    }
+
+    /// Names of the triggers on the table, SQLite and MySQL need one per operation for
+    /// the audit.
+    fn names(&self, backend: Backend) -> Vec<(String, &'static str)> {
+        let name = self.trigger_name();
+        match (&self.kind, backend) {
+            #[cfg(feature = "postgres")]
+            (Kind::Audit { .. }, Backend::Postgres) => {
+                vec![(name, "INSERT OR UPDATE OR DELETE")]
+            },
+            (Kind::Audit { .. }, _) => ["INSERT", "UPDATE", "DELETE"]
+                .into_iter()
+                .map(|operation| {
+                    (format!("{name}_{}", operation.to_lowercase()), operation)
+                })
+                .collect(),
+            _ => vec![(name, "UPDATE")],
+        }
+    }
+
+    /// Statements creating the trigger, Postgres also gets the function it runs.
+    pub fn create(&self, backend: Backend) -> Vec<String> {
+        self.names(backend)
+            .into_iter()
+            .flat_map(|(name, operation)| self.create_trigger(backend, &name, operation))
+            .collect()
+    }
+
+    /// Statements dropping what [`Trigger::create`] created, on Postgres dropping the
+    /// function drops its trigger.
+    pub fn drop(&self, backend: Backend) -> Vec<String> {
+        match backend {
+            #[cfg(feature = "postgres")]
+            Backend::Postgres => vec![format!(
+                "DROP FUNCTION IF EXISTS {}() CASCADE;",
+                self.trigger_name()
+            )],
+            _ => self
+                .names(backend)
+                .into_iter()
+                .map(|(name, _)| format!("DROP TRIGGER IF EXISTS {name};"))
+                .collect(),
+        }
+    }
+
+    fn create_trigger(
+        &self,
+        backend: Backend,
+        name: &str,
+        operation: &str,
+    ) -> Vec<String> {
+        let (table, column, key) = (&self.table, &self.column, &self.key);
+        let row = if operation == "DELETE" { "OLD" } else { "NEW" };
+        match (&self.kind, backend) {
+            (Kind::UpdatedAt, Backend::Sqlite) => vec![format!(
+                "CREATE TRIGGER {name} AFTER UPDATE ON {table} FOR EACH ROW \
+                 WHEN NEW.{column} IS OLD.{column}\nBEGIN\n  \
+                 UPDATE {table} SET {column} = CURRENT_TIMESTAMP \
+                 WHERE {key} = NEW.{key};\nEND;"
+            )],
+            #[cfg(feature = "postgres")]
+            (Kind::UpdatedAt, Backend::Postgres) => self.with_function(
+                format!("NEW.{column} := CURRENT_TIMESTAMP;\n  RETURN NEW;"),
+                &format!("BEFORE UPDATE ON {table} FOR EACH ROW"),
+            ),
+            #[cfg(feature = "mysql")]
+            (Kind::UpdatedAt, Backend::MySql) => vec![format!(
+                "CREATE TRIGGER {name} BEFORE UPDATE ON {table} FOR EACH ROW \
+                 SET NEW.{column} = CURRENT_TIMESTAMP;"
+            )],
+            (Kind::Audit { history }, _) => {
+                let table_name = format!("'{}'", table.replace('\'', "''"));
+                #[cfg(feature = "postgres")]
+                if backend == Backend::Postgres {
+                    return self.with_function(
+                        format!(
+                            "INSERT INTO {history} (table_name, operation, row_key) \
+                             VALUES ({table_name}, TG_OP, CASE WHEN TG_OP = 'DELETE' \
+                             THEN CAST(OLD.{key} AS TEXT) ELSE CAST(NEW.{key} AS TEXT) \
+                             END);\n  RETURN NULL;"
+                        ),
+                        &format!("AFTER {operation} ON {table} FOR EACH ROW"),
+                    );
+                }
+                let insert = format!(
+                    "INSERT INTO {history} (table_name, operation, row_key) \
+                     VALUES ({table_name}, '{operation}', {});",
+                    backend.cast_to_text(&format!("{row}.{key}"))
+                );
+                vec![self.row_trigger(
+                    backend,
+                    name,
+                    &format!("AFTER {operation}"),
+                    "",
+                    &insert,
+                )]
+            },
+            (Kind::CascadeSoftDelete { child, foreign_key }, _) => {
+                let when = format!("OLD.{column} IS NULL AND NEW.{column} IS NOT NULL");
+                let update = format!(
+                    "UPDATE {child} SET {column} = NEW.{column} \
+                     WHERE {foreign_key} = NEW.{key} AND {column} IS NULL"
+                );
+                match backend {
+                    Backend::Sqlite => vec![self.row_trigger(
+                        backend,
+                        name,
+                        &format!("AFTER UPDATE OF {column}"),
+                        &format!(" WHEN {when}"),
+                        &format!("{update};"),
+                    )],
+                    #[cfg(feature = "postgres")]
+                    Backend::Postgres => self.with_function(
+                        format!("{update};\n  RETURN NULL;"),
+                        &format!(
+                            "AFTER UPDATE OF {column} ON {table} FOR EACH ROW WHEN ({when})"
+                        ),
+                    ),
+                    // no WHEN clause, the condition goes into the statement
+                    #[cfg(feature = "mysql")]
+                    Backend::MySql => vec![self.row_trigger(
+                        backend,
+                        name,
+                        "AFTER UPDATE",
+                        "",
+                        &format!("{update} AND {when};"),
+                    )],
+                }
+            },
+        }
+    }
+
+    /// `CREATE TRIGGER` running `statement` for each row, MySQL takes it without a
+    /// `BEGIN ... END` block.
+    fn row_trigger(
+        &self,
+        backend: Backend,
+        name: &str,
+        timing: &str,
+        when: &str,
+        statement: &str,
+    ) -> String {
+        let table = &self.table;
+        match backend {
+            #[cfg(feature = "mysql")]
+            Backend::MySql => {
+                format!(
+                    "CREATE TRIGGER {name} {timing} ON {table} FOR EACH ROW {statement}"
+                )
+            },
+            _ => format!(
+                "CREATE TRIGGER {name} {timing} ON {table} FOR EACH ROW{when}\nBEGIN\n  \
+                 {statement}\nEND;"
+            ),
+        }
+    }
+
+    /// Postgres triggers call a function, created before the trigger and named after
+    /// it.
+    #[cfg(feature = "postgres")]
+    fn with_function(&self, body: String, event: &str) -> Vec<String> {
+        let name = self.trigger_name();
+        vec![
+            format!(
+                "CREATE OR REPLACE FUNCTION {name}() RETURNS trigger AS $$\nBEGIN\n  \
+                 {body}\nEND;\n$$ LANGUAGE plpgsql;"
+            ),
+            format!("CREATE TRIGGER {name} {event} EXECUTE FUNCTION {name}();"),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TestDb;
+
+    #[tokio::test]
+    async fn test_triggers() {
+        let db = TestDb::new(
+            "CREATE TABLE authors (id INTEGER PRIMARY KEY, name TEXT,
+                 updated_at TEXT, deleted_at TEXT);
+             CREATE TABLE posts (id INTEGER PRIMARY KEY, author_id INTEGER,
+                 deleted_at TEXT);
+             CREATE TABLE history (table_name TEXT, operation TEXT, row_key TEXT);
+             INSERT INTO authors (id, name) VALUES (1, 'a');
+             INSERT INTO posts (id, author_id) VALUES (1, 1), (2, 1);",
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            Trigger::updated_at("authors").create(Backend::Sqlite),
+            [
+                "CREATE TRIGGER authors_updated_at AFTER UPDATE ON authors FOR EACH ROW \
+              WHEN NEW.updated_at IS OLD.updated_at\nBEGIN\n  UPDATE authors SET \
+              updated_at = CURRENT_TIMESTAMP WHERE id = NEW.id;\nEND;"
+            ]
+        );
+        let triggers = [
+            Trigger::updated_at("authors"),
+            Trigger::audit("posts", "history"),
+            Trigger::cascade_soft_delete("authors", "posts", "author_id"),
+        ];
+        for statement in triggers.iter().flat_map(|t| t.create(Backend::Sqlite)) {
+            db.execute(&statement).await.unwrap();
+        }
+
+        db.execute("UPDATE authors SET name = 'b';").await.unwrap();
+        db.execute("UPDATE authors SET deleted_at = '2024-01-01';")
+            .await
+            .unwrap();
+        db.execute("DELETE FROM posts WHERE id = 2;").await.unwrap();
+        db.assert_rows(
+            "SELECT updated_at IS NOT NULL, deleted_at FROM authors;",
+            &[&["1", "2024-01-01"]],
+        )
+        .await;
+        db.assert_rows("SELECT id, deleted_at FROM posts;", &[&["1", "2024-01-01"]])
+            .await;
+        db.assert_rows(
+            "SELECT table_name, operation, row_key FROM history;",
+            &[
+                &["posts", "UPDATE", "1"],
+                &["posts", "UPDATE", "2"],
+                &["posts", "DELETE", "2"],
+            ],
+        )
+        .await;
+
+        for statement in triggers.iter().flat_map(|t| t.drop(Backend::Sqlite)) {
+            db.execute(&statement).await.unwrap();
+        }
+        db.assert_rows(
+            "SELECT COUNT(*) FROM sqlite_master WHERE type = 'trigger';",
+            &[&["0"]],
+        )
+        .await;
+    }
+}